@@ -0,0 +1,115 @@
+// Benchmarks of the interpreter core, run with `cargo bench`.
+// Throughput is reported in frames or instructions per second, for whole games running headless
+// and for the parts that dominate them: decoding and drawing.
+#[macro_use]
+extern crate criterion;
+extern crate chipper;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use criterion::{black_box, Criterion, Throughput};
+
+use chipper::cpu::{Chip8, CYCLES_PER_FRAME};
+use chipper::instruction;
+
+// Frames per iteration, one emulated second
+const FRAMES: usize = 60;
+
+const INSTRUCTIONS: usize = 10_000;
+
+const ROMS: [&str; 3] = ["BRIX", "INVADERS", "TETRIS"];
+
+// A fresh machine running a bundled ROM, past the first frames where games set themselves up
+fn machine(name: &str) -> Chip8 {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("roms").join(name);
+    let mut rom = Vec::new();
+    File::open(&path).and_then(|mut f| f.read_to_end(&mut rom)).unwrap();
+
+    let mut cpu = Chip8::new();
+    cpu.seed(1);
+    cpu.load_rom(&rom);
+    for _ in 0..FRAMES {
+        cpu.run_frame(CYCLES_PER_FRAME).unwrap();
+    }
+
+    cpu
+}
+
+fn games(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frames");
+    group.throughput(Throughput::Elements(FRAMES as u64));
+    for name in ROMS.iter() {
+        let mut cpu = machine(name);
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                for _ in 0..FRAMES {
+                    cpu.run_frame(CYCLES_PER_FRAME).unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+
+    // Without timer updates in between, like fast-forward or a batch run
+    let mut group = c.benchmark_group("instructions");
+    group.throughput(Throughput::Elements(INSTRUCTIONS as u64));
+    for name in ROMS.iter() {
+        let mut cpu = machine(name);
+        group.bench_function(*name, |b| {
+            b.iter(|| {
+                for _ in 0..INSTRUCTIONS {
+                    cpu.emulate_cycle().unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(0x10000));
+    group.bench_function("all opcodes", |b| {
+        b.iter(|| {
+            for opcode in 0..=0xFFFF {
+                black_box(instruction::decode(black_box(opcode)));
+            }
+        })
+    });
+    group.finish();
+}
+
+// Memory filled with DXYN drawing a 15 line sprite at (60, 20), so it crosses the right and
+// bottom edges, and a jump back to the start
+fn draw_rom() -> Vec<u8> {
+    let mut ops = vec![0x603C, 0x6114, 0xA000];
+    while ops.len() < (0x1000 - 0x200) / 2 - 1 {
+        ops.push(0xD01F);
+    }
+    ops.push(0x1206);
+
+    ops.iter().flat_map(|op| vec![(op >> 8) as u8, *op as u8]).collect()
+}
+
+fn draw(c: &mut Criterion) {
+    let mut group = c.benchmark_group("draw");
+    group.throughput(Throughput::Elements(INSTRUCTIONS as u64));
+    for &(name, wrap) in &[("clip", false), ("wrap", true)] {
+        let mut cpu = Chip8::new();
+        cpu.quirks.wrap = wrap;
+        cpu.load_rom(&draw_rom());
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for _ in 0..INSTRUCTIONS {
+                    cpu.emulate_cycle().unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, games, decode, draw);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate chipper;
+
+use chipper::cpu::{Chip8, MAX_STACK_DEPTH};
+
+const FRAMES: usize = 600;
+
+// Input layout:
+//   byte 0      quirks (bits 0-5) and address wrapping (bit 6)
+//   byte 1      stack depth
+//   byte 2      number of key events N
+//   N x 3 bytes key events: frame, key (low nibble) and pressed (bit 4)
+//   the rest    ROM image
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 3 {
+        return;
+    }
+
+    let settings = data[0];
+    let events = data[2] as usize;
+    let split = (3 + events * 3).min(data.len());
+    let (keys, rom) = data[3..].split_at(split - 3);
+
+    let mut cpu = Chip8::new();
+    cpu.quirks.shift = settings & 0x01 != 0;
+    cpu.quirks.memory_increment_by_x = settings & 0x02 != 0;
+    cpu.quirks.memory_leave_i_unchanged = settings & 0x04 != 0;
+    cpu.quirks.jump = settings & 0x08 != 0;
+    cpu.quirks.logic = settings & 0x10 != 0;
+    cpu.quirks.wrap = settings & 0x20 != 0;
+    cpu.wrap_addresses = settings & 0x40 != 0;
+    cpu.stack_depth = data[1] as usize % MAX_STACK_DEPTH + 1;
+    cpu.seed(1);
+    cpu.load_rom(rom);
+
+    for frame in 0..FRAMES {
+        for event in keys.chunks(3).filter(|e| e.len() == 3 && e[0] as usize == frame % 256) {
+            let key = (event[1] & 0xF) as usize;
+            if event[2] & 0x10 != 0 {
+                cpu.key_down(key);
+            } else {
+                cpu.key_up(key);
+            }
+        }
+
+        if cpu.run_frame(10).is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,23 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate chipper;
+
+use chipper::cpu::Chip8;
+
+// Frames of 10 instructions, like a game running for 10 seconds
+const FRAMES: usize = 600;
+
+// Any ROM image on the default machine. Faults are fine, they stop the program the way
+// the emulator does; panics, overflows and out-of-bounds accesses are not.
+fuzz_target!(|rom: &[u8]| {
+    let mut cpu = Chip8::new();
+    cpu.seed(1);
+    cpu.load_rom(rom);
+
+    for _ in 0..FRAMES {
+        if cpu.run_frame(10).is_err() {
+            break;
+        }
+    }
+});
@@ -0,0 +1,408 @@
+// chipper as a libretro core, so CHIP-8 programs run in RetroArch and other libretro frontends.
+// The frontend calls the retro_* functions below; the core answers through the callbacks
+// it was given: one video frame, one frame of audio and an input poll for every retro_run.
+
+// The unsafe functions take pointers from the frontend, valid as libretro.h describes them
+#![allow(clippy::missing_safety_doc)]
+
+extern crate chipper;
+
+pub mod ffi;
+
+use std::fs::File;
+use std::io::Read;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::ffi::{CStr, CString};
+use std::sync::Mutex;
+
+use chipper::buzzer::{Buzzer, SAMPLE_RATE};
+use chipper::cpu::{Chip8, CYCLES_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chipper::palette::Palette;
+use ffi::*;
+
+// The SDL frontend's default gamepad layout: directions on the D-pad (2/4/6/8),
+// the main action (5) on the bottom face button, which the RetroPad calls B.
+// The other keys go on the remaining buttons, so every key can be reached.
+const BUTTONS: [(c_uint, usize, &[u8]); 16] = [
+    (RETRO_DEVICE_ID_JOYPAD_UP, 0x2, b"Keypad 2\0"),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, 0x8, b"Keypad 8\0"),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, 0x4, b"Keypad 4\0"),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, 0x6, b"Keypad 6\0"),
+    (RETRO_DEVICE_ID_JOYPAD_B, 0x5, b"Keypad 5\0"),
+    (RETRO_DEVICE_ID_JOYPAD_A, 0x0, b"Keypad 0\0"),
+    (RETRO_DEVICE_ID_JOYPAD_Y, 0x1, b"Keypad 1\0"),
+    (RETRO_DEVICE_ID_JOYPAD_X, 0x3, b"Keypad 3\0"),
+    (RETRO_DEVICE_ID_JOYPAD_L, 0x7, b"Keypad 7\0"),
+    (RETRO_DEVICE_ID_JOYPAD_R, 0x9, b"Keypad 9\0"),
+    (RETRO_DEVICE_ID_JOYPAD_L2, 0xA, b"Keypad A\0"),
+    (RETRO_DEVICE_ID_JOYPAD_R2, 0xB, b"Keypad B\0"),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, 0xC, b"Keypad C\0"),
+    (RETRO_DEVICE_ID_JOYPAD_START, 0xD, b"Keypad D\0"),
+    (RETRO_DEVICE_ID_JOYPAD_L3, 0xE, b"Keypad E\0"),
+    (RETRO_DEVICE_ID_JOYPAD_R3, 0xF, b"Keypad F\0"),
+];
+
+// Two-player games share the keypad, so both controllers have the whole of it
+const PORTS: c_uint = 2;
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+    log: Option<LogPrintfFn>,
+}
+
+struct Core {
+    cpu: Chip8,
+    buzzer: Buzzer,
+    palette: Palette,
+    // XRGB8888 picture handed to the frontend. Only rows the program changed are converted again.
+    video: Vec<u32>,
+    // Set when the program stopped with a fault, until the core is reset
+    stopped: bool,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+    log: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+fn callbacks() -> Callbacks {
+    *CALLBACKS.lock().unwrap()
+}
+
+// Messages go to the frontend's log, or to stderr if it doesn't keep one
+fn log(level: c_uint, message: &str) {
+    match callbacks().log {
+        Some(log) => {
+            let message = CString::new(message.replace('\0', "")).unwrap();
+            unsafe { log(level, b"%s\n\0".as_ptr() as *const c_char, message.as_ptr()) };
+        }
+        None => eprintln!("{}", message),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    let mut interface = LogCallback { log: None };
+    let interface_ptr = &mut interface as *mut LogCallback as *mut c_void;
+    let log = if callback(RETRO_ENVIRONMENT_GET_LOG_INTERFACE, interface_ptr) {
+        interface.log
+    } else {
+        None
+    };
+
+    let mut callbacks = CALLBACKS.lock().unwrap();
+    callbacks.environment = Some(callback);
+    callbacks.log = log;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(callback);
+}
+
+// Audio goes to the frontend a frame at a time through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(callback);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: b"chipper\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: SCREEN_WIDTH as c_uint,
+            base_height: SCREEN_HEIGHT as c_uint,
+            max_width: SCREEN_WIDTH as c_uint,
+            max_height: SCREEN_HEIGHT as c_uint,
+            aspect_ratio: SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32,
+        },
+        timing: SystemTiming {
+            fps: 60.0,
+            sample_rate: SAMPLE_RATE as f64,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() {
+        return false;
+    }
+    let rom = match read_game(&*game) {
+        Some(rom) => rom,
+        None => return false,
+    };
+
+    let environment = match callbacks().environment {
+        Some(environment) => environment,
+        None => return false,
+    };
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+    let format = &mut format as *mut c_uint as *mut c_void;
+    if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, format) {
+        log(RETRO_LOG_ERROR, "The frontend doesn't support XRGB8888 pixels");
+        return false;
+    }
+
+    let mut descriptors: Vec<InputDescriptor> = (0..PORTS)
+        .flat_map(|port| {
+            BUTTONS.iter().map(move |&(id, _, description)| {
+                InputDescriptor {
+                    port,
+                    device: RETRO_DEVICE_JOYPAD,
+                    index: 0,
+                    id,
+                    description: description.as_ptr() as *const c_char,
+                }
+            })
+        })
+        .collect();
+    descriptors.push(InputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: ptr::null(),
+    });
+    environment(RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+                descriptors.as_mut_ptr() as *mut c_void);
+
+    let mut cpu = Chip8::new();
+    cpu.load_rom(&rom);
+    *CORE.lock().unwrap() = Some(Core {
+        cpu,
+        buzzer: Buzzer::new(),
+        palette: Palette::theme("classic").unwrap(),
+        video: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        stopped: false,
+    });
+
+    true
+}
+
+// ROMs are passed in memory, or as a path by frontends that don't load content themselves
+unsafe fn read_game(game: &GameInfo) -> Option<Vec<u8>> {
+    if !game.data.is_null() {
+        return Some(slice::from_raw_parts(game.data as *const u8, game.size).to_vec());
+    }
+    if game.path.is_null() {
+        return None;
+    }
+
+    let path = CStr::from_ptr(game.path).to_string_lossy().into_owned();
+    let mut rom = Vec::new();
+    match File::open(&path).and_then(|mut f| f.read_to_end(&mut rom)) {
+        Ok(_) => Some(rom),
+        Err(e) => {
+            log(RETRO_LOG_ERROR, &format!("Could not load {}: {}", path, e));
+            None
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_type: c_uint,
+                                          _info: *const GameInfo,
+                                          _count: usize)
+                                          -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(ref mut core) = *CORE.lock().unwrap() {
+        core.cpu.reset();
+        core.stopped = false;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let callbacks = callbacks();
+    let mut core = CORE.lock().unwrap();
+    let core = match *core {
+        Some(ref mut core) => core,
+        None => return,
+    };
+
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+    if let Some(input_state) = callbacks.input_state {
+        for &(id, key, _) in BUTTONS.iter() {
+            let pressed = (0..PORTS).any(|port| input_state(port, RETRO_DEVICE_JOYPAD, 0, id) != 0);
+            if pressed {
+                core.cpu.key_down(key);
+            } else {
+                core.cpu.key_up(key);
+            }
+        }
+    }
+
+    // A program stopped by a fault stays on screen and goes quiet, like in the SDL frontend
+    let samples = if core.stopped {
+        core.buzzer.frame(false)
+    } else {
+        match core.buzzer.run_frame(&mut core.cpu, CYCLES_PER_FRAME) {
+            Ok(samples) => samples,
+            Err(fault) => {
+                log(RETRO_LOG_WARN, &format!("Stopped: {} at {:03X}", fault, core.cpu.pc()));
+                core.stopped = true;
+                core.buzzer.frame(false)
+            }
+        }
+    };
+
+    let (foreground, background) = (xrgb(&core.palette, true), xrgb(&core.palette, false));
+    for y in core.cpu.gfx.dirty_rows() {
+        let line = &mut core.video[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH];
+        for (x, pixel) in line.iter_mut().enumerate() {
+            *pixel = if core.cpu.gfx.get(x, y) { foreground } else { background };
+        }
+    }
+    core.cpu.gfx.clear_dirty();
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        video_refresh(core.video.as_ptr() as *const c_void,
+                      SCREEN_WIDTH as c_uint,
+                      SCREEN_HEIGHT as c_uint,
+                      SCREEN_WIDTH * 4);
+    }
+
+    // The buzzer is mono, the frontend takes interleaved stereo
+    let stereo: Vec<i16> = samples.iter().flat_map(|&sample| vec![sample, sample]).collect();
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        audio_sample_batch(stereo.as_ptr(), samples.len());
+    }
+}
+
+fn xrgb(palette: &Palette, lit: bool) -> u32 {
+    let (r, g, b) = if lit { palette.foreground() } else { palette.background() };
+
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+// Save states come from Chip8::save_state and always have the same size
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    CORE.lock().unwrap().as_ref().map_or(0, |core| core.cpu.save_state().len())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let state = match *CORE.lock().unwrap() {
+        Some(ref core) => core.cpu.save_state(),
+        None => return false,
+    };
+    if data.is_null() || size < state.len() {
+        return false;
+    }
+
+    slice::from_raw_parts_mut(data as *mut u8, state.len()).copy_from_slice(&state);
+    true
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let state = slice::from_raw_parts(data as *const u8, size);
+
+    match *CORE.lock().unwrap() {
+        Some(ref mut core) => {
+            match core.cpu.load_state(state) {
+                Ok(()) => {
+                    core.stopped = false;
+                    true
+                }
+                Err(e) => {
+                    log(RETRO_LOG_ERROR, &format!("Could not load the save state: {}", e));
+                    false
+                }
+            }
+        }
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+// Memory isn't exposed: writes from the frontend would bypass the interpreter's
+// instruction cache
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
@@ -0,0 +1,162 @@
+// A minimal libretro frontend: it loads a ROM into the core, runs frames with a button
+// held down and records what the core sends back.
+extern crate chipper_libretro;
+
+use std::os::raw::{c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+use chipper_libretro::*;
+use chipper_libretro::ffi::*;
+
+struct Host {
+    pixel_format: Option<c_uint>,
+    frame: Vec<u32>,
+    frame_size: (c_uint, c_uint, usize),
+    audio_frames: usize,
+    audio_level: i16,
+    // Port and id of the button held down
+    button: Option<(c_uint, c_uint)>,
+}
+
+static HOST: Mutex<Host> = Mutex::new(Host {
+    pixel_format: None,
+    frame: Vec::new(),
+    frame_size: (0, 0, 0),
+    audio_frames: 0,
+    audio_level: 0,
+    button: None,
+});
+
+extern "C" fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match cmd {
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT => {
+            HOST.lock().unwrap().pixel_format = Some(unsafe { *(data as *const c_uint) });
+            true
+        }
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS => true,
+        _ => false,
+    }
+}
+
+extern "C" fn video_refresh(data: *const c_void, width: c_uint, height: c_uint, pitch: usize) {
+    let length = pitch / 4 * height as usize;
+    let pixels = unsafe { slice::from_raw_parts(data as *const u32, length) };
+    let mut host = HOST.lock().unwrap();
+    host.frame = pixels.to_vec();
+    host.frame_size = (width, height, pitch);
+}
+
+extern "C" fn audio_sample(_: i16, _: i16) {}
+
+extern "C" fn audio_sample_batch(data: *const i16, frames: usize) -> usize {
+    let samples = unsafe { slice::from_raw_parts(data, frames * 2) };
+    let mut host = HOST.lock().unwrap();
+    host.audio_frames = frames;
+    host.audio_level = samples.iter().map(|sample| sample.abs()).max().unwrap_or(0);
+    frames
+}
+
+extern "C" fn input_poll() {}
+
+extern "C" fn input_state(port: c_uint, device: c_uint, _index: c_uint, id: c_uint) -> i16 {
+    let pressed = HOST.lock().unwrap().button == Some((port, id));
+    (device == RETRO_DEVICE_JOYPAD && pressed) as i16
+}
+
+fn run(frames: usize, button: Option<(c_uint, c_uint)>) {
+    HOST.lock().unwrap().button = button;
+    for _ in 0..frames {
+        retro_run();
+    }
+}
+
+// Sounds the buzzer, waits for a key and draws its digit in the top left corner
+const ROM: [u8; 14] = [
+    0x61, 0x00, // V1 = 0
+    0x60, 0x3C, // V0 = 60
+    0xF0, 0x18, // sound timer = V0
+    0xF0, 0x0A, // V0 = key
+    0xF0, 0x29, // I = digit V0
+    0xD1, 0x15, // draw it at (V1, V1)
+    0x12, 0x0C, // loop
+];
+
+// The core is a single global instance, so everything is checked in one test
+#[test]
+fn host() {
+    retro_set_environment(environment);
+    retro_set_video_refresh(video_refresh);
+    retro_set_audio_sample(audio_sample);
+    retro_set_audio_sample_batch(audio_sample_batch);
+    retro_set_input_poll(input_poll);
+    retro_set_input_state(input_state);
+    retro_init();
+    assert_eq!(retro_api_version(), RETRO_API_VERSION);
+
+    let game = GameInfo {
+        path: ptr::null(),
+        data: ROM.as_ptr() as *const c_void,
+        size: ROM.len(),
+        meta: ptr::null(),
+    };
+    assert!(unsafe { retro_load_game(&game) });
+    assert_eq!(HOST.lock().unwrap().pixel_format, Some(RETRO_PIXEL_FORMAT_XRGB8888));
+
+    let mut info: SystemAvInfo = unsafe { std::mem::zeroed() };
+    unsafe { retro_get_system_av_info(&mut info) };
+    assert_eq!((info.geometry.base_width, info.geometry.base_height), (64, 32));
+    assert_eq!(info.timing.sample_rate, 44_100.0);
+
+    // One frame of picture and sound per run
+    run(1, None);
+    {
+        let host = HOST.lock().unwrap();
+        assert_eq!(host.frame_size, (64, 32, 64 * 4));
+        assert_eq!(host.audio_frames, 735);
+        assert!(host.audio_level > 0);
+        assert!(host.frame.iter().all(|&pixel| pixel == host.frame[0]));
+    }
+
+    // Save before the key is pressed
+    let size = retro_serialize_size();
+    let mut state = vec![0u8; size];
+    assert!(unsafe { retro_serialize(state.as_mut_ptr() as *mut c_void, size) });
+
+    // FX0A finishes when the button is released. A is keypad 0.
+    run(2, Some((0, RETRO_DEVICE_ID_JOYPAD_A)));
+    run(1, None);
+    let drawn = HOST.lock().unwrap().frame.clone();
+    // The top row of the digit 0 is 1111
+    assert!(drawn[..4].iter().all(|&pixel| pixel != drawn[4]));
+
+    // Loading the state brings back the blank screen, still waiting for a key
+    assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, size) });
+    run(60, None);
+    {
+        let host = HOST.lock().unwrap();
+        assert!(host.frame.iter().all(|&pixel| pixel == host.frame[0]));
+        assert_eq!(host.audio_level, 0);
+    }
+    run(2, Some((0, RETRO_DEVICE_ID_JOYPAD_A)));
+    run(1, None);
+    assert_eq!(HOST.lock().unwrap().frame, drawn);
+
+    // The second controller reaches the keys for player 2, Start is keypad D
+    assert!(unsafe { retro_unserialize(state.as_ptr() as *const c_void, size) });
+    run(2, Some((1, RETRO_DEVICE_ID_JOYPAD_START)));
+    run(1, None);
+    {
+        // The top row of the digit D is 1110
+        let frame = &HOST.lock().unwrap().frame;
+        assert!(frame[..3].iter().all(|&pixel| pixel != frame[4]));
+        assert_eq!(frame[3], frame[4]);
+    }
+
+    // Damaged states are refused
+    assert!(!unsafe { retro_unserialize(state.as_ptr() as *const c_void, size / 2) });
+
+    retro_unload_game();
+    retro_deinit();
+}
@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use buzzer::SAMPLE_RATE;
+
+// Writes the buzzer output into a mono 16-bit WAV file
+pub struct WavRecorder {
+    writer: WavWriter<BufWriter<File>>,
+}
+
+impl WavRecorder {
+    pub fn create(path: &str) -> io::Result<WavRecorder> {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+
+        let writer = WavWriter::create(path, spec).map_err(wav_error)?;
+
+        Ok(WavRecorder { writer })
+    }
+
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for &sample in samples {
+            self.writer.write_sample(sample).map_err(wav_error)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        self.writer.finalize().map_err(wav_error)
+    }
+}
+
+fn wav_error(e: hound::Error) -> io::Error {
+    match e {
+        hound::Error::IoError(e) => e,
+        e => io::Error::other(e),
+    }
+}
@@ -0,0 +1,70 @@
+use std::f32::consts::PI;
+
+use cpu::{Chip8, Fault};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+
+// The buzzer is driven by the 60 Hz sound timer, so audio is produced one frame at a time.
+// 44100 divides evenly by 60, every frame is exactly 735 samples long.
+pub const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / 60) as usize;
+
+// Pitch of the buzzer in Hz
+pub const TONE: u32 = 440;
+
+const AMPLITUDE: f32 = 8_000.0;
+
+// Generates the buzzer signal: a sine wave while the sound timer is active, silence otherwise.
+// The phase keeps running through silent frames, so the signal only depends on the frame number
+// and whether the buzzer was on, which makes recordings reproducible.
+pub struct Buzzer {
+    sample: u64,
+    // Whether the last frame had the tone
+    on: bool,
+}
+
+impl Default for Buzzer {
+    fn default() -> Buzzer {
+        Buzzer::new()
+    }
+}
+
+impl Buzzer {
+    pub fn new() -> Buzzer {
+        Buzzer { sample: 0, on: false }
+    }
+
+    // Emulate one 60 Hz frame on `cpu` and generate its sound. The buzzer is sampled after
+    // the instructions and before the timers tick, so FX18 with N sounds for exactly N frames.
+    pub fn run_frame(&mut self, cpu: &mut Chip8, cycles: usize) -> Result<Vec<i16>, Fault> {
+        cpu.run_cycles(cycles)?;
+        let samples = self.frame(cpu.buzzer());
+        cpu.update_timers();
+
+        Ok(samples)
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    // Mono 16-bit samples for the next frame
+    pub fn frame(&mut self, on: bool) -> Vec<i16> {
+        let mut samples = Vec::with_capacity(SAMPLES_PER_FRAME);
+        self.on = on;
+
+        for _ in 0..SAMPLES_PER_FRAME {
+            if on {
+                // Only the position within a single period matters, keeping the argument small
+                let period = (self.sample * TONE as u64) % SAMPLE_RATE as u64;
+                let phase = 2.0 * PI * period as f32 / SAMPLE_RATE as f32;
+                samples.push((phase.sin() * AMPLITUDE) as i16);
+            } else {
+                samples.push(0);
+            }
+
+            self.sample += 1;
+        }
+
+        samples
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use cpu::Chip8;
+
+// Finding where a game keeps a number (lives, score, time) and holding it at a value.
+//
+// A search starts with every address in memory and narrows them down step by step, e.g. for lives:
+// search for 3, lose a life, search for 2, lose another, search for "decreased".
+// Every step compares memory with what it held at the previous step.
+
+// What a step of a search keeps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Filter {
+    fn keeps(&self, before: u8, now: u8) -> bool {
+        match *self {
+            Filter::Equal(value) => now == value,
+            Filter::Changed => now != before,
+            Filter::Unchanged => now == before,
+            Filter::Increased => now > before,
+            Filter::Decreased => now < before,
+        }
+    }
+}
+
+pub struct Search {
+    candidates: Vec<usize>,
+    // Memory at the previous step
+    snapshot: Vec<u8>,
+}
+
+impl Search {
+    pub fn new(memory: &[u8]) -> Search {
+        Search {
+            candidates: (0..memory.len()).collect(),
+            snapshot: memory.to_vec(),
+        }
+    }
+
+    // Keep the addresses that pass `filter`, returning how many are left
+    pub fn narrow(&mut self, memory: &[u8], filter: Filter) -> usize {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&address| filter.keeps(snapshot[address], memory[address]));
+        self.snapshot = memory.to_vec();
+
+        self.candidates.len()
+    }
+
+    // Addresses left, in order
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+// Addresses frozen to a value: they are written before every frame,
+// whatever the program does with them in between
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cheats {
+    frozen: BTreeMap<u16, u8>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats::default()
+    }
+
+    pub fn freeze(&mut self, address: u16, value: u8) {
+        self.frozen.insert(address, value);
+    }
+
+    // Returns whether the address was frozen
+    pub fn unfreeze(&mut self, address: u16) -> bool {
+        self.frozen.remove(&address).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frozen.is_empty()
+    }
+
+    // Frozen addresses with their values, in order
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.frozen.iter().map(|(&address, &value)| (address, value))
+    }
+
+    // Written like the program would, so instructions frozen in memory get decoded again
+    pub fn apply(&self, cpu: &mut Chip8) {
+        for (&address, &value) in &self.frozen {
+            cpu.poke(address as usize, value);
+        }
+    }
+}
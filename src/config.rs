@@ -0,0 +1,85 @@
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+// User settings live in ~/.chipper (or $CHIPPER_HOME when set):
+//
+// ~/.chipper/config.toml       - global defaults
+// ~/.chipper/roms/<ROM>.toml   - per-ROM overrides, named after the ROM file
+// ~/.chipper/cheats/<ROM>.toml - addresses frozen with the console, saved by chipper
+//
+// Each file is split into sections (e.g. [keymap]) owned by the module that uses them.
+pub fn config_dir() -> Option<PathBuf> {
+    match env::var_os("CHIPPER_HOME") {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => env::var_os("HOME").map(|home| Path::new(&home).join(".chipper")),
+    }
+}
+
+pub fn global_config() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+pub fn rom_config(rom: &str) -> Option<PathBuf> {
+    rom_file("roms", rom)
+}
+
+pub fn cheats_file(rom: &str) -> Option<PathBuf> {
+    rom_file("cheats", rom)
+}
+
+fn rom_file(dir: &str, rom: &str) -> Option<PathBuf> {
+    let name = match Path::new(rom).file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return None,
+    };
+
+    config_dir().map(|config| config.join(dir).join(format!("{}.toml", name)))
+}
+
+// Read a single section from a config file.
+// A missing file or section is not an error, it simply yields None.
+pub fn read_section(path: &Path, section: &str) -> Result<Option<Value>, String> {
+    let mut contents = String::new();
+    match File::open(path) {
+        Ok(mut file) => {
+            file.read_to_string(&mut contents)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        Err(_) => return Ok(None),
+    }
+
+    let value = contents.parse::<Value>().map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    Ok(value.get(section).cloned())
+}
+
+// Bindings map a CHIP-8 key (hex digit) to one name or a list of names:
+//
+// 5 = ["W", "Up"]
+// 8 = "S"
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Names {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Names {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            Names::One(name) => vec![name],
+            Names::Many(names) => names,
+        }
+    }
+}
+
+pub fn parse_chip_key(name: &str) -> Result<usize, String> {
+    let digits = name.trim_start_matches("0x");
+    match usize::from_str_radix(digits, 16) {
+        Ok(key) if key < 16 => Ok(key),
+        _ => Err(format!("\"{}\" is not a CHIP-8 key (0-F)", name)),
+    }
+}
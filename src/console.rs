@@ -0,0 +1,148 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use chipper::cheats::{Filter, Search};
+use game::{self, Game};
+
+// Candidates listed at most, a search has to be narrowed further to see the rest
+const MAX_LISTED: usize = 20;
+
+const HELP: &str = "\
+search                  start a new search with every address
+search N                keep the addresses holding N
+search changed          keep the addresses that changed since the last search,
+search unchanged        ... stayed the same,
+search increased        ... went up,
+search decreased        ... or went down
+list                    show the addresses left with their values
+freeze ADDRESS VALUE    write VALUE to ADDRESS (hex) before every frame
+unfreeze ADDRESS        stop writing to ADDRESS
+cheats                  show the frozen addresses
+save                    save the frozen addresses, they are loaded with the ROM next time
+help                    show this";
+
+// Commands typed into the terminal while playing (--console), for finding and changing
+// the numbers a game keeps in memory. Lines are read on their own thread,
+// so the window keeps running while waiting for input.
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    pub fn start() -> Console {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        println!("Console ready, type help for the commands");
+
+        Console { lines }
+    }
+
+    // Run the commands typed since the last call
+    pub fn poll(&self, mut game: Option<&mut Game>) {
+        while let Ok(line) = self.lines.try_recv() {
+            let result = match game {
+                Some(ref mut game) => run(&line, game),
+                None if line.trim().is_empty() => Ok(()),
+                None => Err("Start a game first".to_string()),
+            };
+            if let Err(e) = result {
+                println!("{}", e);
+            }
+        }
+    }
+}
+
+fn run(line: &str, game: &mut Game) -> Result<(), String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        [] => {}
+        ["help"] => println!("{}", HELP),
+        ["search"] => {
+            game.search = Some(Search::new(game.cpu.memory()));
+            println!("Searching {} addresses", game.cpu.memory().len());
+        }
+        ["search", filter] => {
+            let filter = parse_filter(filter)?;
+            let memory = game.cpu.memory();
+            match game.search {
+                Some(ref mut search) => {
+                    let left = search.narrow(memory, filter);
+                    println!("{} addresses left", left);
+                    if left <= MAX_LISTED {
+                        list(search, memory);
+                    }
+                }
+                // Comparing needs a step before, so the first one only takes a snapshot
+                None if !matches!(filter, Filter::Equal(_)) => {
+                    game.search = Some(Search::new(memory));
+                    println!("Started a new search, search again once the value changed");
+                }
+                None => {
+                    let mut search = Search::new(memory);
+                    println!("{} addresses left", search.narrow(memory, filter));
+                    game.search = Some(search);
+                }
+            }
+        }
+        ["list"] => {
+            let search = game.search.as_ref().ok_or_else(|| "No search going on".to_string())?;
+            list(search, game.cpu.memory());
+        }
+        ["freeze", address, value] => {
+            let address = game::parse_address(address)?;
+            let value = parse_number(value)?;
+            game.cheats.freeze(address, value);
+            println!("{:03X} frozen to {}", address, value);
+        }
+        ["unfreeze", address] => {
+            let address = game::parse_address(address)?;
+            if !game.cheats.unfreeze(address) {
+                return Err(format!("{:03X} isn't frozen", address));
+            }
+            println!("{:03X} unfrozen", address);
+        }
+        ["cheats"] if game.cheats.is_empty() => println!("No frozen addresses"),
+        ["cheats"] => println!("{}", game::describe_cheats(&game.cheats)),
+        ["save"] => println!("Cheats saved to {}", game.save_cheats()?),
+        _ => return Err(format!("Unknown command: {}, type help for the commands", line.trim())),
+    }
+
+    Ok(())
+}
+
+fn list(search: &Search, memory: &[u8]) {
+    for &address in search.candidates().iter().take(MAX_LISTED) {
+        println!("  {:03X}: {}", address, memory[address]);
+    }
+    if search.candidates().len() > MAX_LISTED {
+        println!("  and {} more", search.candidates().len() - MAX_LISTED);
+    }
+}
+
+fn parse_filter(filter: &str) -> Result<Filter, String> {
+    match filter {
+        "changed" => Ok(Filter::Changed),
+        "unchanged" => Ok(Filter::Unchanged),
+        "increased" => Ok(Filter::Increased),
+        "decreased" => Ok(Filter::Decreased),
+        _ => parse_number(filter).map(Filter::Equal),
+    }
+}
+
+// Values are decimal, or hex with 0x
+fn parse_number(number: &str) -> Result<u8, String> {
+    let parsed = match number.strip_prefix("0x") {
+        Some(digits) => u8::from_str_radix(digits, 16),
+        None => number.parse::<u8>(),
+    };
+
+    parsed.map_err(|_| format!("\"{}\" is not a byte (0-255)", number))
+}
@@ -1,12 +1,88 @@
+use std::fmt;
 use std::fs::File;
-use std::io::Read;
-use std::time::Duration;
-use rand::random;
-use rodio::{get_default_endpoint, play_raw};
-use rodio::source::SineWave;
-use rodio::Source;
+use std::io::{self, BufWriter, Read};
+use rand::{self, Rng, SeedableRng, XorShiftRng};
 
 use font::FONTSET;
+use framebuffer::Framebuffer;
+use image;
+use instruction::{self, Instruction};
+use palette::Palette;
+
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+
+pub const MEMORY_SIZE: usize = 4096;
+
+// Instructions executed per 60 Hz frame (600 instructions per second) by default.
+// The frontend runs ROMs the database recommends a different speed for faster or slower.
+pub const CYCLES_PER_FRAME: usize = 10;
+
+// The COSMAC VIP interpreter had room for 12 return addresses, later ones for 16
+pub const VIP_STACK_DEPTH: usize = 12;
+pub const MAX_STACK_DEPTH: usize = 16;
+
+// Save states start with this, the last byte being the format version
+const STATE_MAGIC: &[u8; 4] = b"C8S1";
+
+// Behaviour that differs between CHIP-8 implementations.
+// Names follow the community chip-8-database, the defaults keep chipper's original behaviour.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VX in place instead of storing the shifted VY in VX
+    pub shift: bool,
+    // FX55/FX65 increase I by X instead of X + 1
+    pub memory_increment_by_x: bool,
+    // FX55/FX65 leave I unchanged
+    pub memory_leave_i_unchanged: bool,
+    // BNNN jumps to XNN plus VX instead of NNN plus V0
+    pub jump: bool,
+    // 8XY1/8XY2/8XY3 reset VF to zero
+    pub logic: bool,
+    // DXYN wraps sprites around the edges of the screen instead of clipping them
+    pub wrap: bool,
+}
+
+// Errors that stop the emulated program.
+// The faulting instruction has no effect on registers, memory, the stack or the screen.
+// Only `opcode` is set to it, and a program counter past the end of memory is wrapped
+// around first when addresses wrap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    UnknownOpcode(u16),
+    // 2NNN with all stack levels in use
+    StackOverflow,
+    // 00EE with nothing on the stack
+    StackUnderflow,
+    // Access past the end of memory with address wrapping turned off
+    AddressOutOfRange(usize),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::UnknownOpcode(opcode) => write!(f, "unknown opcode {:04X}", opcode),
+            Fault::StackOverflow => write!(f, "stack overflow"),
+            Fault::StackUnderflow => write!(f, "return with an empty stack"),
+            Fault::AddressOutOfRange(address) => {
+                write!(f, "address {:X} is outside of memory", address)
+            }
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            shift: false,
+            memory_increment_by_x: false,
+            memory_leave_i_unchanged: true,
+            jump: false,
+            logic: false,
+            wrap: true,
+        }
+    }
+}
 
 pub struct Chip8 {
     // The systems memory map:
@@ -19,7 +95,10 @@ pub struct Chip8 {
     opcode: u16,
 
     // The Chip 8 has 4K memory in total
-    memory: [u8; 4000],
+    memory: [u8; MEMORY_SIZE],
+    // Instructions decoded so far with their opcodes, by address.
+    // Entries are dropped when the program writes over them.
+    cache: Vec<Option<(u16, Instruction)>>,
 
     // CPU registers: The Chip 8 has 15 8-bit general purpose registers named V0,V1 up to VE.
     // The 16th register is used  for the ‘carry flag’.
@@ -31,10 +110,9 @@ pub struct Chip8 {
     pc: u16,
 
     // The graphics of the Chip 8 are black and white
-    // and the screen has a total of 2048 pixels (64 x 32).
-    // This can easily be implemented using an array that hold the pixel state (1 or 0):
-    pub gfx: [u8; 64 * 32],
-    pub draw_flag: bool,
+    // and the screen has a total of 2048 pixels (64 x 32), one bit each.
+    // The framebuffer records which rows changed, so frontends know when to redraw.
+    pub gfx: Framebuffer,
 
     // Interupts and hardware registers.
     // The Chip 8 has none, but there are two timer registers that count at 60 Hz.
@@ -52,402 +130,651 @@ pub struct Chip8 {
     // store the program counter in the stack before proceeding.
     // The system has 16 levels of stack and in order to remember which level of the stack is used,
     // you need to implement a stack pointer (sp).
-    stack: [u16; 16],
+    stack: [u16; MAX_STACK_DEPTH],
     sp: u16,
+    // Number of stack levels available to the program (at most 16)
+    pub stack_depth: usize,
+
+    // Addresses past the end of memory wrap around to 0x000 like on the original hardware.
+    // With wrapping off they stop the program with a fault instead.
+    pub wrap_addresses: bool,
 
     // Finally, the Chip 8 has a HEX based keypad (0x0-0xF),
     // you can use an array to store the current state of the key.
-    pub key: [u8; 16],
+    // It changes through key_down and key_up, which also catch the releases FX0A waits for.
+    key: [u8; 16],
+    // Set while FX0A is waiting, until a key is released
+    waiting_for_key: bool,
+    released_key: Option<u8>,
+
+    pub quirks: Quirks,
+
+    // The loaded program, kept to restart it on reset
+    rom: Vec<u8>,
+
+    // Source of CXNN's random numbers, seeded randomly unless a seed is set
+    rng: XorShiftRng,
+    seed: Option<u32>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
         // Initialize memory and registers
-        let mut mem: [u8; 4000] = [0; 4000];
+        let mut mem: [u8; MEMORY_SIZE] = [0; MEMORY_SIZE];
 
         // Load fonts to memory
-        for f in 0..FONTSET.len() {
-            mem[f] = FONTSET[f];
-        }
+        mem[..FONTSET.len()].copy_from_slice(&FONTSET);
 
         Chip8 {
             opcode: 0,
             memory: mem,
+            cache: vec![None; MEMORY_SIZE],
             v: [0; 16],
             i: 0,
             pc: 0x200, // program counter starts at 0x200
-            gfx: [0; 64 * 32],
-            draw_flag: false,
+            gfx: Framebuffer::new(),
             delay_timer: 0,
             sound_timer: 0,
-            stack: [0; 16],
+            stack: [0; MAX_STACK_DEPTH],
             sp: 0,
+            stack_depth: MAX_STACK_DEPTH,
+            wrap_addresses: true,
             key: [0; 16],
+            waiting_for_key: false,
+            released_key: None,
+            quirks: Quirks::default(),
+            rom: Vec::new(),
+            rng: rand::weak_rng(),
+            seed: None,
         }
     }
 
-    pub fn load_game(&mut self, game: &str) {
-        // Load game file to memory
-        let mut file = File::open(game).unwrap();
-        // Start loading at 0x200 (512)
-        let size = file.read(&mut self.memory[0x200..]).unwrap();
+    // Make CXNN return the same numbers on every run
+    pub fn seed(&mut self, seed: u32) {
+        // The generator must not be seeded with all zeros
+        self.rng = XorShiftRng::from_seed([seed, 0x193a_6754, 0xa8a7_d469, 0x9783_0e05]);
+        self.seed = Some(seed);
+    }
 
-        println!("Game {} loaded ({} bytes)", game, size);
+    // Restart the loaded program from a clean machine, like a power cycle.
+    // The machine settings and the keys held down are kept.
+    pub fn reset(&mut self) {
+        let mut cpu = Chip8::new();
+        cpu.quirks = self.quirks;
+        cpu.stack_depth = self.stack_depth;
+        cpu.wrap_addresses = self.wrap_addresses;
+        if let Some(seed) = self.seed {
+            cpu.seed(seed);
+        }
+        cpu.key = self.key;
+        cpu.load_rom(&self.rom);
+
+        *self = cpu;
     }
 
-    pub fn emulate_cycle(&mut self) {
-        // Fetch opcode
-        let first = self.memory[self.pc as usize] as u16;
-        let second = self.memory[(self.pc + 1) as usize] as u16;
-        self.opcode = first << 8 | second;
-        println!("Opcode fetched: {:x}", self.opcode);
+    pub fn key_down(&mut self, key: usize) {
+        self.key[key] = 1;
+    }
 
-        let vx = (self.opcode & 0x0F00) >> 8;
-        let vy = (self.opcode & 0x00F0) >> 4;
-        let nn = self.opcode & 0x00FF;
-        let address = self.opcode & 0x0FFF;
+    pub fn key_up(&mut self, key: usize) {
+        // Only the first release counts, FX0A returns a single key
+        if self.key[key] == 1 && self.waiting_for_key && self.released_key.is_none() {
+            self.released_key = Some(key as u8);
+        }
+        self.key[key] = 0;
+    }
 
-        // Decode & execute opcode
-        match self.opcode & 0xF000 {
-            0x0000 => {
-                match self.opcode & 0x000F {
-                    0x0000 => {
-                        self.gfx = [0; 64 * 32];
+    // Address of the next instruction
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
 
-                        self.draw_flag = true;
-                        self.pc += 2;
-                        println!("Clear screen")
-                    }
-                    0x000E => {
-                        self.sp -= 1;
-                        self.pc = self.stack[self.sp as usize];
+    // The instruction executed last
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
 
-                        self.pc += 2;
-                        println!("Return from subroutine");
-                    }
-                    _ => panic!("Unknown opcode: {:x}", self.opcode),
-                }
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn set_v(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    // Return addresses of the subroutines being run, the innermost last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    // Change memory from outside the program, e.g. from a debugger.
+    // Addresses past the end of memory wrap around.
+    pub fn poke(&mut self, address: usize, value: u8) {
+        self.write(address % MEMORY_SIZE, value);
+    }
+
+    // Resolution of the emulated display in pixels (width, height)
+    pub fn resolution(&self) -> (usize, usize) {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    }
+
+    // Save the current contents of the screen as a PNG image,
+    // each CHIP-8 pixel becoming a `scale` x `scale` square in the colours of `palette`
+    pub fn export_png(&self, path: &str, scale: usize, palette: &Palette) -> io::Result<()> {
+        let file = File::create(path)?;
+
+        image::write_png(BufWriter::new(file),
+                         &self.gfx.to_bytes(),
+                         self.resolution(),
+                         scale,
+                         palette)
+    }
+
+    pub fn load_game(&mut self, game: &str) -> io::Result<Vec<u8>> {
+        // Load game file to memory
+        let mut rom = Vec::new();
+        File::open(game)?.read_to_end(&mut rom)?;
+        self.load_rom(&rom);
+
+        println!("Game {} loaded ({} bytes)", game, rom.len());
+        Ok(rom)
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        // Start loading at 0x200 (512), anything that doesn't fit in memory is cut off
+        let size = rom.len().min(self.memory.len() - 0x200);
+        self.memory[0x200..0x200 + size].copy_from_slice(&rom[..size]);
+        self.cache = vec![None; MEMORY_SIZE];
+        self.rom = rom.to_vec();
+    }
+
+    // Memory index of `address`, following the address wrapping setting
+    fn address(&self, address: usize) -> Result<usize, Fault> {
+        if address < MEMORY_SIZE {
+            Ok(address)
+        } else if self.wrap_addresses {
+            Ok(address % MEMORY_SIZE)
+        } else {
+            Err(Fault::AddressOutOfRange(address))
+        }
+    }
+
+    pub fn emulate_cycle(&mut self) -> Result<(), Fault> {
+        // Fetch opcode. A wrapped program counter is kept wrapped,
+        // so it can't run off the end of u16.
+        let pc = self.address(self.pc as usize)?;
+        self.pc = pc as u16;
+        let (opcode, instruction) = match self.cache[pc] {
+            Some(entry) => entry,
+            None => self.fetch(pc)?,
+        };
+        self.opcode = opcode;
+
+        // Execute opcode
+        match instruction {
+            Instruction::ClearScreen => {
+                self.gfx.clear();
+                self.pc += 2;
             }
-            0x1000 => {
-                self.pc = address;
-                println!("Jump to {:x}", self.pc);
+            Instruction::Return => {
+                if self.sp == 0 {
+                    return Err(Fault::StackUnderflow);
+                }
+                self.sp -= 1;
+                self.pc = self.stack[self.sp as usize].wrapping_add(2);
             }
-            0x2000 => {
+            Instruction::Jump(address) => self.pc = address,
+            Instruction::Call(address) => {
+                if self.sp as usize >= self.stack_depth.min(MAX_STACK_DEPTH) {
+                    return Err(Fault::StackOverflow);
+                }
+
                 // Store current program counter on the stack
                 self.stack[self.sp as usize] = self.pc;
 
                 // Increase the stack pointer to prevent overwriting the current stack
                 self.sp += 1;
 
-                self.pc = address;
-                println!("Call subroutine at {:x}", self.pc);
                 // Because it is a subroutine, we should not increase program counter
+                self.pc = address;
             }
-            0x3000 => {
-                if self.v[vx as usize] == nn as u8 {
-                    println!("Register V[{:x}] is equal to {:x}. Skipping the next instruction",
-                             vx,
-                             nn);
-                    self.pc += 4;
-                } else {
-                    println!("Register V[{:x}] is NOT equal to {:x}", vx, nn);
-                    self.pc += 2;
-                }
+            Instruction::SkipIfEqual(x, nn) => self.skip_if(self.v[x as usize] == nn),
+            Instruction::SkipIfNotEqual(x, nn) => self.skip_if(self.v[x as usize] != nn),
+            Instruction::SkipIfRegistersEqual(x, y) => {
+                self.skip_if(self.v[x as usize] == self.v[y as usize])
             }
-            0x4000 => {
-                if self.v[vx as usize] != nn as u8 {
-                    println!("Register V[{:x}] is NOT equal to {:x}. Skipping the next \
-                              instruction",
-                             vx,
-                             nn);
-                    self.pc += 4;
-                } else {
-                    println!("Register V[{:x}] is equal to {:x}", vx, nn);
-                    self.pc += 2;
-                }
+            Instruction::Set(x, nn) => {
+                self.v[x as usize] = nn;
+                self.pc += 2;
             }
-            0x5000 => {
-                if self.v[vx as usize] == self.v[vy as usize] {
-                    println!("Register V[{:x}] is equal to V[{:x}]. Skipping the next instruction",
-                             vx,
-                             vy);
-                    self.pc += 4;
-                } else {
-                    println!("Register V[{:x}] is NOT equal to V[{:x}]", vx, vy);
-                    self.pc += 2;
-                }
+            Instruction::Add(x, nn) => {
+                self.v[x as usize] = self.v[x as usize].wrapping_add(nn);
+                self.pc += 2;
             }
-            0x6000 => {
-                self.v[vx as usize] = nn as u8;
+            Instruction::Copy(x, y) => {
+                self.v[x as usize] = self.v[y as usize];
                 self.pc += 2;
-                println!("Set V[{:x}] to {:x}", vx, nn);
             }
-            0x7000 => {
-                self.v[vx as usize] = self.v[vx as usize].wrapping_add(nn as u8);
-
+            Instruction::Or(x, y) => {
+                self.v[x as usize] |= self.v[y as usize];
+                self.reset_flag_after_logic();
                 self.pc += 2;
-                println!("Add {:x} to V[{:x}]", nn, vx);
             }
-            0x8000 => {
-                match self.opcode & 0x000F {
-                    0x0000 => {
-                        self.v[vx as usize] = self.v[vy as usize];
-
-                        self.pc += 2;
-                        println!("Set V[{:x}] to the value of V[{:x}]", vx, vy);
-                    }
-                    0x0001 => {
-                        self.v[vx as usize] = self.v[vx as usize] | self.v[vy as usize];
-
-                        self.pc += 2;
-                        println!("Set V[{:x}] to V[{:x}] or V[{:x}] (Bitwise OR)", vx, vx, vy);
-                    }
-                    0x0002 => {
-                        self.v[vx as usize] = self.v[vx as usize] & self.v[vy as usize];
-
-                        self.pc += 2;
-                        println!("Set V[{:x}] to V[{:x}] and V[{:x}] (Bitwise AND)",
-                                 vx,
-                                 vx,
-                                 vy);
-                    }
-                    0x0003 => {
-                        self.v[vx as usize] = self.v[vx as usize] ^ self.v[vy as usize];
-
-                        self.pc += 2;
-                        println!("Set V[{:x}] to V[{:x}] xor V[{:x}]", vx, vx, vy);
-                    }
-                    0x0004 => {
-                        let (value, overflow) = self.v[vx as usize]
-                            .overflowing_add(self.v[vy as usize]);
-                        if overflow {
-                            self.v[0xF] = 1;
-                        } else {
-                            self.v[0xF] = 0;
-                        }
-                        self.v[vx as usize] = value;
-
-                        self.pc += 2;
-                        println!("Add V[{:x}] to V[{:x}]", vy, vx);
-                    }
-                    0x0005 => {
-                        let (value, overflow) = self.v[vx as usize]
-                            .overflowing_sub(self.v[vy as usize]);
-                        if overflow {
-                            self.v[0xF] = 0;
-                        } else {
-                            self.v[0xF] = 1;
-                        }
-                        self.v[vx as usize] = value;
-
-                        self.pc += 2;
-                        println!("Subtract V[{:x}] from V[{:x}]", vy, vx);
-                    }
-                    0x0006 => {
-                        self.v[0xF] = self.v[vy as usize] & 0x1;
-                        self.v[vx as usize] = self.v[vy as usize] >> 1;
-
-                        self.pc += 2;
-                        println!("Shift V[{:x}] right by one and copy the result to V[{:x}]",
-                                 vy,
-                                 vx);
-                    }
-                    0x0007 => {
-                        let (value, overflow) = self.v[vy as usize]
-                            .overflowing_sub(self.v[vx as usize]);
-                        if overflow {
-                            self.v[0xF] = 0;
-                        } else {
-                            self.v[0xF] = 1;
-                        }
-                        self.v[vx as usize] = value;
-
-                        self.pc += 2;
-                        println!("Set V[{:x}] to V[{:x}] minus V[{:x}]", vx, vy, vx);
-                    }
-                    0x000E => {
-                        self.v[0xF] = self.v[vy as usize] & 0x80;
-                        let (value, _) = self.v[vy as usize].overflowing_shl(1);
-                        self.v[vx as usize] = value;
-
+            Instruction::And(x, y) => {
+                self.v[x as usize] &= self.v[y as usize];
+                self.reset_flag_after_logic();
+                self.pc += 2;
+            }
+            Instruction::Xor(x, y) => {
+                self.v[x as usize] ^= self.v[y as usize];
+                self.reset_flag_after_logic();
+                self.pc += 2;
+            }
+            // The flag is written last, so it wins when VF is also the target
+            Instruction::AddRegisters(x, y) => {
+                let (value, overflow) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.v[x as usize] = value;
+                self.v[0xF] = overflow as u8;
+                self.pc += 2;
+            }
+            Instruction::Subtract(x, y) => {
+                let (value, overflow) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
+                self.v[x as usize] = value;
+                self.v[0xF] = !overflow as u8;
+                self.pc += 2;
+            }
+            Instruction::ShiftRight(x, y) => {
+                let source = if self.quirks.shift { x } else { y };
+                let value = self.v[source as usize];
+                self.v[x as usize] = value >> 1;
+                self.v[0xF] = value & 0x1;
+                self.pc += 2;
+            }
+            Instruction::SubtractFrom(x, y) => {
+                let (value, overflow) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
+                self.v[x as usize] = value;
+                self.v[0xF] = !overflow as u8;
+                self.pc += 2;
+            }
+            Instruction::ShiftLeft(x, y) => {
+                let source = if self.quirks.shift { x } else { y };
+                let value = self.v[source as usize];
+                self.v[x as usize] = value << 1;
+                self.v[0xF] = value >> 7;
+                self.pc += 2;
+            }
+            Instruction::SkipIfRegistersNotEqual(x, y) => {
+                self.skip_if(self.v[x as usize] != self.v[y as usize])
+            }
+            Instruction::SetIndex(address) => {
+                self.i = address;
+                self.pc += 2;
+            }
+            Instruction::JumpWithOffset(address, x) => {
+                let offset = if self.quirks.jump { x } else { 0x0 };
+                self.pc = address + self.v[offset as usize] as u16;
+            }
+            Instruction::Random(x, nn) => {
+                self.v[x as usize] = self.rng.gen::<u8>() & nn;
+                self.pc += 2;
+            }
+            Instruction::Draw(x, y, height) => self.draw(x, y, height as usize)?,
+            Instruction::SkipIfKey(x) => {
+                // Only the low nibble selects the key, as on the COSMAC VIP
+                let key = (self.v[x as usize] & 0xF) as usize;
+                self.skip_if(self.key[key] == 1)
+            }
+            Instruction::SkipIfNotKey(x) => {
+                let key = (self.v[x as usize] & 0xF) as usize;
+                self.skip_if(self.key[key] != 1)
+            }
+            Instruction::GetDelay(x) => {
+                self.v[x as usize] = self.delay_timer;
+                self.pc += 2;
+            }
+            Instruction::WaitForKey(x) => {
+                // Like on the COSMAC VIP the program waits until a key is pressed and
+                // released again. A key held down already counts once it is released.
+                match self.released_key.take() {
+                    Some(k) => {
+                        self.v[x as usize] = k;
+                        self.waiting_for_key = false;
                         self.pc += 2;
-                        println!("Shift V[{:x}] left by one and copy the result to V[{:x}]",
-                                 vy,
-                                 vx);
                     }
-                    _ => panic!("Unknown opcode: {:x}", self.opcode),
+                    None => self.waiting_for_key = true,
                 }
             }
-            0x9000 => {
-                if self.v[vx as usize] != self.v[vy as usize] {
-                    println!("V[{:x}] is NOT equal to V[{:x}]. Skipping the next instruction",
-                             vx,
-                             vy);
-                    self.pc += 4;
-                } else {
-                    println!("V[{:x}] is equal to V[{:x}]", vx, vy);
-                    self.pc += 2;
-                }
+            Instruction::SetDelay(x) => {
+                self.delay_timer = self.v[x as usize];
+                self.pc += 2;
             }
-            0xA000 => {
-                self.i = address;
+            Instruction::SetSound(x) => {
+                self.sound_timer = self.v[x as usize];
                 self.pc += 2;
-                println!("Set I to {:x}", address);
             }
-            0xB000 => {
-                self.pc = address + self.v[0x0] as u16;
-                println!("Jumps to the address {:x} plus V0.", address);
+            Instruction::AddToIndex(x) => {
+                // I is 16 bits wide and wraps around, memory accesses through it
+                // follow the address wrapping setting
+                self.i = self.i.wrapping_add(self.v[x as usize] as u16);
+                self.pc += 2;
             }
-            0xC000 => {
-                self.v[vx as usize] = (random::<u8>() as u16 & nn) as u8;
+            Instruction::Digit(x) => {
+                self.i = self.v[x as usize] as u16 * 5;
                 self.pc += 2;
-                println!("Random value saved in V[{:x}]", vx);
-            }
-            0xD000 => {
-                let x = self.v[vx as usize] as u16;
-                let y = self.v[vy as usize] as u16;
-                let height = self.opcode & 0x000F;
-                println!("Draw to screen. Lines: {}, starting at x={}, y={}",
-                         height,
-                         x,
-                         y);
-
-                self.v[0xF] = 0;
-                for line in 0..height {
-                    let data = self.memory[(self.i + line) as usize];
-
-                    for b in 0..8 {
-                        if (data & (0x80 >> b)) != 0 {
-                            let mut pixel = x + b as u16 + ((y + line) * 64) as u16;
-
-                            // Check for overflow
-                            if pixel >= 64 * 32 {
-                                pixel -= 64 * 32;
-                            }
-
-                            if self.gfx[pixel as usize] == 1 {
-                                self.v[0xF] = 1;
-                            }
-
-                            self.gfx[pixel as usize] ^= 1;
-                        }
-                    }
+            }
+            Instruction::Bcd(x) => {
+                let addresses = [self.address(self.i as usize)?,
+                                 self.address(self.i as usize + 1)?,
+                                 self.address(self.i as usize + 2)?];
+                let value = self.v[x as usize];
+                self.write(addresses[0], value / 100);
+                self.write(addresses[1], (value / 10) % 10);
+                self.write(addresses[2], value % 10);
+                self.pc += 2;
+            }
+            Instruction::Store(x) => {
+                // Check the last address first, so a fault doesn't leave a partial copy
+                self.address(self.i as usize + x as usize)?;
+                for register in 0..x as usize + 1 {
+                    let address = self.address(self.i as usize + register)?;
+                    let value = self.v[register];
+                    self.write(address, value);
                 }
-
-                self.draw_flag = true;
+                self.increment_i_after_memory_access(x as u16);
                 self.pc += 2;
             }
-            0xE000 => {
-                match self.opcode & 0x00FF {
-                    0x009E => {
-                        if self.key[self.v[vx as usize] as usize] == 1 {
-                            println!("Key {:x} is pressed. Skipping the next instruction", vx);
-                            self.pc += 4;
-                        } else {
-                            println!("Key {:x} is NOT pressed", vx);
-                            self.pc += 2;
-                        }
-                    }
-                    0x00A1 => {
-                        if self.key[self.v[vx as usize] as usize] != 1 {
-                            println!("Key {:x} is NOT pressed. Skipping the next instruction", vx);
-                            self.pc += 4;
-                        } else {
-                            println!("Key {:x} is pressed", vx);
-                            self.pc += 2;
-                        }
-                    }
-                    _ => panic!("Unknown opcode: {:x}", self.opcode),
+            Instruction::Load(x) => {
+                self.address(self.i as usize + x as usize)?;
+                for register in 0..x as usize + 1 {
+                    let address = self.address(self.i as usize + register)?;
+                    self.v[register] = self.memory[address];
                 }
+                self.increment_i_after_memory_access(x as u16);
+                self.pc += 2;
             }
-            0xF000 => {
-                match self.opcode & 0x00FF {
-                    0x0007 => {
-                        self.v[vx as usize] = self.delay_timer;
+            Instruction::Unknown(opcode) => return Err(Fault::UnknownOpcode(opcode)),
+        };
 
-                        self.pc += 2;
-                        println!("Set V[{:x}] to value of delay timer ({})",
-                                 vx,
-                                 self.delay_timer);
-                    }
-                    0x000A => {
-                        for k in 0..self.key.len() {
-                            if self.key[k] > 0 {
-                                self.v[vx as usize] = k as u8;
-
-                                self.pc += 2;
-                                println!("Key '{:x}' pressed. Set V[{:x}] to {:x}", k, vx, k);
-                            }
-                        }
-                    }
-                    0x0015 => {
-                        self.delay_timer = self.v[vx as usize];
+        Ok(())
+    }
 
-                        self.pc += 2;
-                        println!("Set delay timer to \"{}\"", vx);
-                    }
-                    0x0018 => {
-                        self.sound_timer = self.v[vx as usize];
+    // Read and decode the instruction at `pc`, keeping it in the cache.
+    // An instruction that wraps around the end of memory isn't cached,
+    // reading it depends on the address wrapping setting.
+    fn fetch(&mut self, pc: usize) -> Result<(u16, Instruction), Fault> {
+        let next = self.address(pc + 1)?;
+        let opcode = (self.memory[pc] as u16) << 8 | self.memory[next] as u16;
+        let entry = (opcode, instruction::decode(opcode));
+        if next == pc + 1 {
+            self.cache[pc] = Some(entry);
+        }
 
-                        self.pc += 2;
-                        println!("Set sound timer to \"{}\"", vx);
-                    }
-                    0x001E => {
-                        self.i += self.v[vx as usize] as u16;
+        Ok(entry)
+    }
 
-                        self.pc += 2;
-                        println!("Add V[{:x}] to I", vx);
-                    }
-                    0x0029 => {
-                        self.i = self.v[vx as usize] as u16 * 5;
+    // Write to memory from the program, dropping the decoded instructions it overwrites
+    fn write(&mut self, address: usize, value: u8) {
+        self.memory[address] = value;
+        self.cache[address] = None;
+        self.cache[(address + MEMORY_SIZE - 1) % MEMORY_SIZE] = None;
+    }
 
-                        self.pc += 2;
-                        println!("Set I to address of digit \"{}\"", vx);
-                    }
-                    0x0033 => {
-                        self.memory[self.i as usize] = self.v[vx as usize] / 100;
-                        self.memory[self.i as usize + 1] = (self.v[vx as usize] / 10) % 10;
-                        self.memory[self.i as usize + 2] = (self.v[vx as usize] % 100) % 10;
+    fn skip_if(&mut self, condition: bool) {
+        self.pc += if condition { 4 } else { 2 };
+    }
 
-                        self.pc += 2;
-                        println!("Binary-coded decimal saved into memory");
-                    }
-                    0x0055 => {
-                        for i in 0..vx + 1 {
-                            self.memory[(self.i + i) as usize] = self.v[i as usize];
-                        }
+    // 8XY1/8XY2/8XY3 reset VF on the COSMAC VIP
+    fn reset_flag_after_logic(&mut self) {
+        if self.quirks.logic {
+            self.v[0xF] = 0;
+        }
+    }
 
-                        self.pc += 2;
-                        println!("Store V[0] - V[{:x}] in memory, starting at I", vx);
-                    }
-                    0x0065 => {
-                        for i in 0..vx + 1 {
-                            self.v[i as usize] = self.memory[(self.i + i) as usize];
-                        }
-                        self.pc += 2;
-                        println!("V[0] - V[{:x}] values have been replaced with {:x}",
-                                 vx,
-                                 self.memory[self.i as usize]);
-                    }
-                    _ => panic!("Unknown opcode: {:x}", self.opcode),
-                }
+    fn draw(&mut self, vx: u8, vy: u8, height: usize) -> Result<(), Fault> {
+        // The starting position always wraps around
+        let x = self.v[vx as usize] as usize % SCREEN_WIDTH;
+        let y = self.v[vy as usize] as usize % SCREEN_HEIGHT;
+
+        // Sprite data is read before drawing, so a fault leaves the screen untouched
+        let mut sprite = [0; 15];
+        for (line, data) in sprite.iter_mut().enumerate().take(height) {
+            *data = self.memory[self.address(self.i as usize + line)?];
+        }
+
+        self.v[0xF] = 0;
+        for (line, &data) in sprite.iter().enumerate().take(height) {
+            // Pixels past the edges wrap around or are clipped, depending on the quirk
+            let row = y + line;
+            if row >= SCREEN_HEIGHT && !self.quirks.wrap {
+                break;
             }
-            _ => panic!("opcode has not been implemented yet"),
-        };
 
-        // Update timers
+            // Line up the sprite with the screen row, leftmost pixel in the highest bit
+            let bits = (data as u64) << (SCREEN_WIDTH - 8);
+            let bits = if self.quirks.wrap {
+                bits.rotate_right(x as u32)
+            } else {
+                bits >> x
+            };
+
+            if self.gfx.xor_row(row % SCREEN_HEIGHT, bits) {
+                self.v[0xF] = 1;
+            }
+        }
+
+        self.pc += 2;
+        Ok(())
+    }
+
+    // FX55 and FX65 move I past the registers they accessed on the original interpreter
+    fn increment_i_after_memory_access(&mut self, vx: u16) {
+        if self.quirks.memory_leave_i_unchanged {
+            return;
+        }
+
+        if self.quirks.memory_increment_by_x {
+            self.i = self.i.wrapping_add(vx);
+        } else {
+            self.i = self.i.wrapping_add(vx + 1);
+        }
+    }
+
+    // Timers count down at 60 Hz, independently of how many instructions run in between
+    pub fn update_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
         if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                let endpoint = get_default_endpoint().unwrap();
-                let source = SineWave::new(440).take_duration(Duration::from_millis(200));
-
-                play_raw(&endpoint, source);
-            }
             self.sound_timer -= 1;
         }
     }
+
+    // Emulate one 60 Hz frame: `cycles` instructions followed by a timer update.
+    // A fault ends the frame early.
+    pub fn run_frame(&mut self, cycles: usize) -> Result<(), Fault> {
+        self.run_cycles(cycles)?;
+
+        self.update_timers();
+        Ok(())
+    }
+
+    // The instructions of a frame, without the timer update
+    pub fn run_cycles(&mut self, cycles: usize) -> Result<(), Fault> {
+        for _ in 0..cycles {
+            self.emulate_cycle()?;
+        }
+
+        Ok(())
+    }
+
+    // The buzzer sounds for as long as the sound timer is above zero
+    pub fn buzzer(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // Snapshot of the running program: memory, registers, stack, timers, screen and
+    // a pending FX0A. It is loaded back into a Chip8 with the same ROM and machine settings,
+    // which are not part of it. Neither are the keys held down or the random number generator.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::new();
+        state.extend_from_slice(STATE_MAGIC);
+        state.extend_from_slice(&self.memory);
+        state.extend_from_slice(&self.v);
+        let pc = self.saved_pc();
+        for value in [self.opcode, self.i, pc, self.sp].iter().chain(self.stack.iter()) {
+            state.extend_from_slice(&value.to_le_bytes());
+        }
+        state.push(self.delay_timer);
+        state.push(self.sound_timer);
+        for row in self.gfx.rows() {
+            state.extend_from_slice(&row.to_le_bytes());
+        }
+        state.push(self.waiting_for_key as u8);
+        state.push(self.released_key.unwrap_or(0xFF));
+
+        state
+    }
+
+    // The program counter can be past the end of memory between instructions, e.g. after
+    // a skip at the last address or a jump with an offset. With wrapping it is saved the way
+    // the next instruction will see it, without it as the end of memory: the next
+    // instruction faults either way.
+    fn saved_pc(&self) -> u16 {
+        if self.wrap_addresses {
+            self.pc % MEMORY_SIZE as u16
+        } else {
+            self.pc.min(MEMORY_SIZE as u16)
+        }
+    }
+
+    // Nothing changes unless the whole state is valid
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let mut reader = StateReader { data: state };
+        if reader.bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err("not a chipper save state".to_string());
+        }
+
+        let mut memory = [0; MEMORY_SIZE];
+        memory.copy_from_slice(reader.bytes(MEMORY_SIZE)?);
+        let mut v = [0; 16];
+        v.copy_from_slice(reader.bytes(16)?);
+        let opcode = reader.u16()?;
+        let i = reader.u16()?;
+        let pc = reader.u16()?;
+        let sp = reader.u16()?;
+        let mut stack = [0; MAX_STACK_DEPTH];
+        for entry in stack.iter_mut() {
+            *entry = reader.u16()?;
+        }
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let mut gfx = Framebuffer::new();
+        for y in 0..SCREEN_HEIGHT {
+            gfx.xor_row(y, reader.u64()?);
+        }
+        let waiting_for_key = reader.u8()? != 0;
+        let released_key = match reader.u8()? {
+            0xFF => None,
+            key => Some(key),
+        };
+
+        if !reader.data.is_empty() {
+            return Err("save state is too long".to_string());
+        }
+        // Return addresses are always those of a call, which ran from memory
+        let in_memory = |address: &u16| (*address as usize) < MEMORY_SIZE;
+        if sp as usize > MAX_STACK_DEPTH ||
+           pc as usize > MEMORY_SIZE ||
+           !stack.iter().all(in_memory) ||
+           released_key.is_some_and(|key| key > 0xF) {
+            return Err("save state is damaged".to_string());
+        }
+
+        self.memory = memory;
+        self.cache = vec![None; MEMORY_SIZE];
+        self.v = v;
+        self.opcode = opcode;
+        self.i = i;
+        self.pc = pc;
+        self.sp = sp;
+        self.stack = stack;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.gfx = gfx;
+        self.waiting_for_key = waiting_for_key;
+        self.released_key = released_key;
+
+        Ok(())
+    }
+}
+
+struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < count {
+            return Err("save state is too short".to_string());
+        }
+
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
 }
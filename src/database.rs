@@ -0,0 +1,29 @@
+use std::sync::OnceLock;
+use romdb::{self, Database, RomInfo};
+
+use config;
+
+// The user's database and the bundled one, parsed on the first lookup.
+// The menu looks up every ROM in the directory.
+static DATABASES: OnceLock<Result<(Option<Database>, Database), String>> = OnceLock::new();
+
+// A full copy of the community database can be put into ~/.chipper/database/
+fn user() -> Result<Option<Database>, String> {
+    match config::config_dir() {
+        Some(dir) => Database::read(&dir.join("database")),
+        None => Ok(None),
+    }
+}
+
+// Look a ROM up in the user's database first, then in the bundled one
+pub fn lookup(rom: &[u8]) -> Result<Option<RomInfo>, String> {
+    let sha1 = romdb::sha1(rom);
+    let databases = DATABASES.get_or_init(|| Ok((user()?, Database::bundled())));
+    let (user, bundled) = databases.as_ref().map_err(|e| e.clone())?;
+
+    if let Some(info) = user.as_ref().and_then(|db| db.find(&sha1)) {
+        return Ok(Some(info));
+    }
+
+    Ok(bundled.find(&sha1))
+}
@@ -0,0 +1,119 @@
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+use cpu::Chip8;
+use palette::{self, Palette, Rgb};
+use phosphor::Phosphor;
+
+pub fn color((r, g, b): Rgb) -> Color {
+    Color::RGB(r, g, b)
+}
+
+// Part of the window the emulated screen is drawn into.
+// The screen is scaled by a whole number so every CHIP-8 pixel has the same size,
+// and centered with the leftover space used as letterbox bars.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub scale: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Viewport {
+    // Fit a screen of `resolution` pixels into a window of `output` pixels
+    pub fn fit(output: (u32, u32), resolution: (usize, usize)) -> Viewport {
+        let (screen_width, screen_height) = (resolution.0 as u32, resolution.1 as u32);
+        let scale = (output.0 / screen_width).min(output.1 / screen_height).max(1);
+
+        let width = screen_width * scale;
+        let height = screen_height * scale;
+
+        Viewport {
+            x: (output.0 as i32 - width as i32) / 2,
+            y: (output.1 as i32 - height as i32) / 2,
+            scale,
+            width,
+            height,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+}
+
+// RGBA bytes in memory order, which SDL names by the order within a 32-bit pixel
+fn rgba_format() -> PixelFormatEnum {
+    if cfg!(target_endian = "little") {
+        PixelFormatEnum::ABGR8888
+    } else {
+        PixelFormatEnum::RGBA8888
+    }
+}
+
+// The emulated screen on the GPU. The texture is made once and updated on every redraw,
+// and only made again if the emulated resolution changes.
+pub struct ScreenTexture<'a> {
+    creator: &'a TextureCreator<WindowContext>,
+    texture: Option<(Texture<'a>, (usize, usize))>,
+}
+
+impl<'a> ScreenTexture<'a> {
+    pub fn new(creator: &'a TextureCreator<WindowContext>) -> ScreenTexture<'a> {
+        ScreenTexture {
+            creator,
+            texture: None,
+        }
+    }
+
+    fn get(&mut self, resolution: (usize, usize)) -> &mut Texture<'a> {
+        if self.texture.as_ref().map(|&(_, size)| size) != Some(resolution) {
+            let texture = self.creator
+                .create_texture_streaming(rgba_format(), resolution.0 as u32, resolution.1 as u32)
+                .unwrap();
+            self.texture = Some((texture, resolution));
+        }
+
+        &mut self.texture.as_mut().unwrap().0
+    }
+}
+
+// Draw the emulated screen, letterboxed into the window
+pub fn draw_screen(canvas: &mut Canvas<Window>,
+                   screen: &mut ScreenTexture,
+                   cpu: &Chip8,
+                   palette: &Palette,
+                   phosphor: Option<&Phosphor>) {
+    // The layout is recomputed on every redraw,
+    // so it follows both window resizes and changes of the emulated resolution
+    let (width, height) = cpu.resolution();
+    let viewport = Viewport::fit(canvas.output_size().unwrap(), (width, height));
+
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+
+    // Unlit pixels that are still fading out are drawn in between
+    // the background and pixel colours. The afterglow has no levels before its first update.
+    let rgba = match phosphor {
+        Some(phosphor) => {
+            let mut rgba = Vec::with_capacity(width * height * 4);
+            for (i, lit) in cpu.gfx.pixels().enumerate() {
+                let fading = phosphor.levels().get(i).cloned().unwrap_or(0.0);
+                let level = if lit { 1.0 } else { fading };
+                let (r, g, b) = palette::blend(palette.background(), palette.foreground(), level);
+                rgba.extend_from_slice(&[r, g, b, 255]);
+            }
+            rgba
+        }
+        None => cpu.gfx.to_rgba(palette),
+    };
+
+    // The whole screen goes to the GPU as one small texture, scaled up when it is copied
+    let texture = screen.get((width, height));
+    texture.update(None, &rgba, width * 4).unwrap();
+    canvas.copy(texture, None, viewport.rect()).unwrap();
+}
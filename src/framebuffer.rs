@@ -0,0 +1,104 @@
+use cpu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use palette::Palette;
+
+// The CHIP-8 screen with one bit per pixel. Each row is a u64 with the leftmost pixel
+// in the highest bit, so a sprite line is drawn with a shift and a single XOR.
+//
+// Rows that change are recorded, so a frontend can tell whether (and which part of)
+// the screen has to be redrawn since it last looked.
+#[derive(Clone)]
+pub struct Framebuffer {
+    rows: [u64; SCREEN_HEIGHT],
+    // Bit n is set when row n changed since the last clear_dirty
+    dirty: u32,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+// Screens are equal when they show the same pixels, whatever has been redrawn since
+impl PartialEq for Framebuffer {
+    fn eq(&self, other: &Framebuffer) -> bool {
+        self.rows == other.rows
+    }
+}
+
+impl Framebuffer {
+    // A blank screen. All rows start out dirty, as nothing has been drawn yet.
+    pub fn new() -> Framebuffer {
+        Framebuffer {
+            rows: [0; SCREEN_HEIGHT],
+            dirty: !0,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.rows[y] & (1 << (SCREEN_WIDTH - 1 - x)) != 0
+    }
+
+    // Pixels of one row, bit 63 being x = 0
+    pub fn row(&self, y: usize) -> u64 {
+        self.rows[y]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = u64> + '_ {
+        self.rows.iter().cloned()
+    }
+
+    // Every pixel, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows.iter().flat_map(|&row| (0..SCREEN_WIDTH).rev().map(move |b| row & (1 << b) != 0))
+    }
+
+    pub fn clear(&mut self) {
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            if *row != 0 {
+                *row = 0;
+                self.dirty |= 1 << y;
+            }
+        }
+    }
+
+    // XOR `bits` into row `y`, returning whether a lit pixel was switched off
+    pub fn xor_row(&mut self, y: usize, bits: u64) -> bool {
+        let collision = self.rows[y] & bits != 0;
+        self.rows[y] ^= bits;
+        if bits != 0 {
+            self.dirty |= 1 << y;
+        }
+
+        collision
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty != 0
+    }
+
+    // Rows changed since the last clear_dirty, from top to bottom
+    pub fn dirty_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..SCREEN_HEIGHT).filter(move |&y| self.dirty & (1 << y) != 0)
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = 0;
+    }
+
+    // One byte per pixel, 0 or 1, for code that works with palette indices
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.pixels().map(|pixel| pixel as u8).collect()
+    }
+
+    // RGBA bytes in the colours of `palette`, ready to be uploaded as a texture
+    pub fn to_rgba(&self, palette: &Palette) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+        for pixel in self.pixels() {
+            let (r, g, b) = if pixel { palette.foreground() } else { palette.background() };
+            rgba.extend_from_slice(&[r, g, b, 255]);
+        }
+
+        rgba
+    }
+}
@@ -0,0 +1,341 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml::Value;
+
+use audio::WavRecorder;
+use buzzer::Buzzer;
+use cheats::{Cheats, Search};
+use config;
+use database;
+use cpu::{Chip8, Fault, CYCLES_PER_FRAME, MAX_STACK_DEPTH, MEMORY_SIZE};
+use gamepad::GamepadMap;
+use input::HeldKeys;
+use keymap::Keymap;
+use palette::Palette;
+use recording::GifRecorder;
+use romdb::{self, RomInfo};
+
+// Machine settings can be given in the global config and per ROM,
+// on top of what the ROM database says about the ROM's platform:
+//
+// [machine]
+// stack_depth = 12         # levels of subroutine calls, 1-16
+// wrap_addresses = false   # stop the program on accesses past 0xFFF instead of wrapping around
+// wrap_sprites = true      # wrap sprites around the edges of the screen instead of clipping them
+#[derive(Deserialize)]
+struct MachineConfig {
+    stack_depth: Option<usize>,
+    wrap_addresses: Option<bool>,
+    wrap_sprites: Option<bool>,
+}
+
+// A ROM loaded into a fresh Chip8, with the input mapping and recordings that belong to it
+pub struct Game {
+    pub path: String,
+    // Title from the ROM database, or the file name
+    pub title: String,
+    pub cpu: Chip8,
+    pub keymap: Keymap,
+    pub gamepad: GamepadMap,
+    // Inputs holding CHIP-8 keys down
+    pub held: HeldKeys,
+    pub cycles_per_frame: usize,
+    // Set when the program stopped with a fault, until the game is reset
+    pub fault: Option<Fault>,
+    // Frozen addresses, loaded from the ROM's cheats file
+    pub cheats: Cheats,
+    // Memory search going on in the console
+    pub search: Option<Search>,
+
+    buzzer: Buzzer,
+    gif_recorder: Option<GifRecorder>,
+    audio_recorder: Option<WavRecorder>,
+}
+
+impl Game {
+    pub fn load(path: &str, keymap_preset: Option<&str>) -> Result<Game, String> {
+        println!("Using input file: {}", path);
+
+        let mut keymap = Keymap::load(path, keymap_preset)
+            .map_err(|e| format!("Could not load keymap: {}", e))?;
+        let gamepad = GamepadMap::load(path)
+            .map_err(|e| format!("Could not load gamepad mapping: {}", e))?;
+
+        // Initialize the Chip8 system and load the game into the memory
+        let mut cpu = Chip8::new();
+        let rom = cpu.load_game(path).map_err(|e| format!("Could not load {}: {}", path, e))?;
+
+        // Known ROMs get the settings they need to run properly
+        let mut title = Path::new(path).file_name()
+            .map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned());
+        let mut cycles_per_frame = CYCLES_PER_FRAME;
+        match database::lookup(&rom) {
+            Ok(Some(info)) => {
+                keymap.bind_actions(&info.keys);
+                print_rom_info(&info, &keymap);
+                title = info.title.clone();
+                cpu.quirks = info.quirks;
+                if let Some(depth) = info.stack_depth {
+                    cpu.stack_depth = depth;
+                }
+                if let Some(tickrate) = info.tickrate {
+                    cycles_per_frame = tickrate;
+                }
+            }
+            Ok(None) => println!("ROM not found in the database (SHA-1 {})", romdb::sha1(&rom)),
+            Err(e) => println!("Could not read the ROM database: {}", e),
+        }
+
+        for config in config::global_config().into_iter().chain(config::rom_config(path)) {
+            if let Some(section) = config::read_section(&config, "machine")? {
+                apply_machine_config(&mut cpu, section)
+                    .map_err(|e| format!("{}: {}", config.display(), e))?;
+            }
+        }
+
+        let cheats = load_cheats(path)?;
+        if !cheats.is_empty() {
+            println!("Cheats: {}", describe_cheats(&cheats));
+        }
+
+        Ok(Game {
+            path: path.to_string(),
+            title,
+            cpu,
+            keymap,
+            gamepad,
+            held: HeldKeys::new(),
+            cycles_per_frame,
+            fault: None,
+            cheats,
+            search: None,
+            buzzer: Buzzer::new(),
+            gif_recorder: None,
+            audio_recorder: None,
+        })
+    }
+
+    // Emulate one 60 Hz frame and add it to the active recordings.
+    // A recording that fails is stopped and reported, the game goes on.
+    // A game stopped by a fault doesn't run any further.
+    pub fn frame(&mut self, palette: &Palette) -> Result<(), String> {
+        if self.fault.is_some() {
+            return Ok(());
+        }
+        self.cheats.apply(&mut self.cpu);
+        let samples = match self.buzzer.run_frame(&mut self.cpu, self.cycles_per_frame) {
+            Ok(samples) => samples,
+            Err(fault) => return Err(self.stop(fault)),
+        };
+
+        let gfx = self.cpu.gfx.to_bytes();
+        let gif = self.gif_recorder.as_mut().map(|r| r.capture(&gfx, palette));
+        if let Some(Err(e)) = gif {
+            self.gif_recorder = None;
+            return Err(format!("GIF recording stopped: {}", e));
+        }
+
+        let audio = self.audio_recorder.as_mut().map(|r| r.write(&samples));
+        if let Some(Err(e)) = audio {
+            self.audio_recorder = None;
+            return Err(format!("Audio recording stopped: {}", e));
+        }
+
+        Ok(())
+    }
+
+    // Whether the buzzer sounded in the last frame
+    pub fn is_buzzing(&self) -> bool {
+        self.buzzer.is_on()
+    }
+
+    // Execute a single instruction
+    pub fn step(&mut self) -> Result<(), String> {
+        if self.fault.is_some() {
+            return Ok(());
+        }
+
+        self.cpu.emulate_cycle().map_err(|fault| self.stop(fault))
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.fault = None;
+    }
+
+    fn stop(&mut self, fault: Fault) -> String {
+        self.fault = Some(fault);
+
+        format!("{} stopped: {} at {:03X}", self.title, fault, self.cpu.pc())
+    }
+
+    pub fn start_gif(&mut self, path: &str, scale: usize, palette: &Palette) -> Result<(), String> {
+        let recorder = GifRecorder::create(path, self.cpu.resolution(), scale, palette)
+            .map_err(|e| format!("Could not start recording: {}", e))?;
+        self.gif_recorder = Some(recorder);
+
+        Ok(())
+    }
+
+    // Returns whether there was a recording to save
+    pub fn stop_gif(&mut self) -> Result<bool, String> {
+        match self.gif_recorder.take() {
+            Some(recorder) => {
+                recorder.finish().map_err(|e| format!("Could not save recording: {}", e))?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn is_recording_gif(&self) -> bool {
+        self.gif_recorder.is_some()
+    }
+
+    pub fn start_audio(&mut self, path: &str) -> Result<(), String> {
+        let recorder = WavRecorder::create(path)
+            .map_err(|e| format!("Could not record audio: {}", e))?;
+        self.audio_recorder = Some(recorder);
+
+        Ok(())
+    }
+
+    pub fn stop_audio(&mut self) -> Result<bool, String> {
+        match self.audio_recorder.take() {
+            Some(recorder) => {
+                recorder.finish().map_err(|e| format!("Could not save audio recording: {}", e))?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // Returns where the cheats were saved
+    pub fn save_cheats(&self) -> Result<String, String> {
+        let path = config::cheats_file(&self.path)
+            .ok_or_else(|| "Could not find the home directory".to_string())?;
+        let mut contents = "# Addresses (hex) written with a value before every frame\n[freeze]\n"
+            .to_string();
+        for (address, value) in self.cheats.iter() {
+            contents.push_str(&format!("\"{:03X}\" = {}\n", address, value));
+        }
+
+        path.parent().map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| File::create(&path))
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|e| format!("Could not save cheats to {}: {}", path.display(), e))?;
+
+        Ok(path.display().to_string())
+    }
+
+    // Screenshots and recordings are named after the ROM and the time they were taken,
+    // e.g. PONG2-1508012345123.png
+    pub fn output_path(&self, extension: &str) -> String {
+        let name = Path::new(&self.path).file_stem().map(|n| n.to_string_lossy().into_owned());
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        format!("{}-{}{:03}.{}",
+                name.unwrap_or_else(|| "chipper".to_string()),
+                time.as_secs(),
+                time.subsec_millis(),
+                extension)
+    }
+}
+
+impl Drop for Game {
+    // Recordings are finished whichever way the game ends
+    fn drop(&mut self) {
+        for result in &[self.stop_gif(), self.stop_audio()] {
+            match *result {
+                Ok(true) => println!("Recording saved"),
+                Ok(false) => {}
+                Err(ref e) => println!("{}", e),
+            }
+        }
+    }
+}
+
+fn apply_machine_config(cpu: &mut Chip8, section: Value) -> Result<(), String> {
+    let settings: MachineConfig = section.try_into().map_err(|e| e.to_string())?;
+
+    if let Some(depth) = settings.stack_depth {
+        if depth == 0 || depth > MAX_STACK_DEPTH {
+            return Err(format!("stack_depth has to be between 1 and {}", MAX_STACK_DEPTH));
+        }
+        cpu.stack_depth = depth;
+    }
+    if let Some(wrap) = settings.wrap_addresses {
+        cpu.wrap_addresses = wrap;
+    }
+    if let Some(wrap) = settings.wrap_sprites {
+        cpu.quirks.wrap = wrap;
+    }
+
+    Ok(())
+}
+
+// Cheats files list frozen addresses in hex with their values:
+//
+// [freeze]
+// "2F4" = 3
+fn load_cheats(rom: &str) -> Result<Cheats, String> {
+    let mut cheats = Cheats::new();
+    let path = match config::cheats_file(rom) {
+        Some(path) => path,
+        None => return Ok(cheats),
+    };
+    let section = match config::read_section(&path, "freeze")? {
+        Some(section) => section,
+        None => return Ok(cheats),
+    };
+
+    let invalid = |e: String| format!("{}: {}", path.display(), e);
+    let table = section.as_table()
+        .ok_or_else(|| invalid("[freeze] has to be a table".to_string()))?;
+    for (address, value) in table {
+        let address = parse_address(address).map_err(invalid)?;
+        match value.as_integer() {
+            Some(value) if (0..=0xFF).contains(&value) => cheats.freeze(address, value as u8),
+            _ => return Err(invalid(format!("the value of {:03X} has to be a byte", address))),
+        }
+    }
+
+    Ok(cheats)
+}
+
+// Memory addresses are written in hex, with or without 0x
+pub fn parse_address(address: &str) -> Result<u16, String> {
+    match u16::from_str_radix(address.trim_start_matches("0x"), 16) {
+        Ok(address) if (address as usize) < MEMORY_SIZE => Ok(address),
+        _ => Err(format!("\"{}\" is not a memory address (000-FFF)", address)),
+    }
+}
+
+pub fn describe_cheats(cheats: &Cheats) -> String {
+    let cheats: Vec<String> = cheats.iter()
+        .map(|(address, value)| format!("{:03X} = {}", address, value))
+        .collect();
+
+    cheats.join(", ")
+}
+
+fn print_rom_info(info: &RomInfo, keymap: &Keymap) {
+    let mut credits = info.authors.join(", ");
+    if let Some(ref release) = info.release {
+        credits = format!("{} ({})", credits, release);
+    }
+
+    println!("{} by {} [SHA-1 {}]", info.title, credits, info.sha1);
+    if let Some(ref description) = info.description {
+        println!("{}", description);
+    }
+    if let Some(ref platform) = info.platform {
+        println!("Platform: {}, {:?}", platform, info.quirks);
+    }
+
+    for (action, &key) in &info.keys {
+        println!("  {:>12}: {:X} ({})", action, key, keymap.names(key as usize).join(", "));
+    }
+}
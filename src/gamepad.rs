@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use sdl2::controller::{Axis, Button};
+use toml::Value;
+
+use config::{self, Names};
+
+// Default layout puts the directions on the D-pad the way most CHIP-8 games read them
+// (2/4/6/8) and the main action on A (5).
+const DEFAULT: [(Button, usize); 8] = [
+    (Button::DPadUp, 0x2),
+    (Button::DPadDown, 0x8),
+    (Button::DPadLeft, 0x4),
+    (Button::DPadRight, 0x6),
+    (Button::A, 0x5),
+    (Button::B, 0x0),
+    (Button::X, 0x1),
+    (Button::Y, 0x3),
+];
+
+// The left stick works like the D-pad once pushed this far (of 32767) in a direction
+const STICK_THRESHOLD: i16 = 16000;
+
+// Gamepad sections use SDL's controller button names
+// (a, b, x, y, back, start, leftshoulder, rightshoulder, dpup, dpdown, dpleft, dpright, ...):
+//
+// [gamepad]
+// default = false
+//
+// [gamepad.buttons]
+// 1 = "dpup"
+// 4 = ["dpdown", "a"]
+//
+// With `default = false` the built-in layout is dropped and only the listed buttons are used.
+#[derive(Deserialize)]
+struct GamepadConfig {
+    default: Option<bool>,
+    #[serde(default)]
+    buttons: HashMap<String, Names>,
+}
+
+pub struct GamepadMap {
+    buttons: HashMap<Button, usize>,
+}
+
+impl GamepadMap {
+    pub fn new() -> GamepadMap {
+        GamepadMap { buttons: DEFAULT.iter().cloned().collect() }
+    }
+
+    // Same layering as the keymap: defaults, then the global config, then the ROM's own config
+    pub fn load(rom: &str) -> Result<GamepadMap, String> {
+        let mut gamepad = GamepadMap::new();
+        for path in config::global_config().into_iter().chain(config::rom_config(rom)) {
+            if let Some(section) = config::read_section(&path, "gamepad")? {
+                gamepad.apply(section).map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+        }
+
+        Ok(gamepad)
+    }
+
+    fn apply(&mut self, section: Value) -> Result<(), String> {
+        let settings: GamepadConfig = section.try_into().map_err(|e| e.to_string())?;
+
+        match settings.default {
+            Some(true) => self.buttons = GamepadMap::new().buttons,
+            Some(false) => self.buttons.clear(),
+            None => {}
+        }
+
+        for (chip_key, names) in settings.buttons {
+            let key = config::parse_chip_key(&chip_key)?;
+
+            self.buttons.retain(|_, k| *k != key);
+            for name in names.into_vec() {
+                let button = Button::from_string(&name)
+                    .ok_or_else(|| format!("Unknown gamepad button \"{}\"", name))?;
+                self.buttons.insert(button, key);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, button: Button) -> Option<usize> {
+        self.buttons.get(&button).cloned()
+    }
+}
+
+// The D-pad directions along a left stick axis, with whether the stick is pushed towards them
+pub fn stick_directions(axis: Axis, value: i16) -> Option<[(Button, bool); 2]> {
+    let (negative, positive) = match axis {
+        Axis::LeftX => (Button::DPadLeft, Button::DPadRight),
+        Axis::LeftY => (Button::DPadUp, Button::DPadDown),
+        _ => return None,
+    };
+
+    Some([(negative, value <= -STICK_THRESHOLD), (positive, value >= STICK_THRESHOLD)])
+}
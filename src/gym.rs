@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+use serde_json::{self, Map, Value};
+
+use cpu::{Chip8, Fault, CYCLES_PER_FRAME, MEMORY_SIZE};
+
+// An interface for training agents on CHIP-8 games, in the style of OpenAI Gym:
+// reset() starts an episode and returns the first observation, step(action) plays
+// a few frames with the keys of the action held down and says how that went.
+//
+// Observations are the screen, one byte per pixel (0 or 1), row by row.
+// Rewards and the end of an episode come from the game's memory, as described by its Rules.
+
+// Frames played per step, unless set otherwise
+const FRAME_SKIP: usize = 4;
+
+// More digits don't fit into a u32
+const MAX_DIGITS: usize = 9;
+
+// Rules for the games in roms/ that keep their score in memory, by file name.
+// Rules files map game names to their rules, every field can be left out:
+//
+// "BRIX": {
+//   "actions": [[], [4], [6]],                   keys held down for each action
+//   "score": {"address": "314", "digits": 3},    a hex address, with digits for BCD numbers
+//   "lives": {"address": "3F0"},
+//   "endOnHalt": true
+// }
+const BUNDLED_RULES: &str = include_str!("../data/gym.json");
+
+static BUNDLED: OnceLock<HashMap<String, Rules>> = OnceLock::new();
+
+// Where a game keeps a number in memory
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Number {
+    // A single byte
+    Byte(u16),
+    // Decimal digits, one per byte with the most significant first, the way FX33 stores them
+    Bcd(u16, usize),
+}
+
+impl Number {
+    // Bytes that aren't decimal digits make a bigger number rather than an overflow
+    fn read(&self, memory: &[u8]) -> u32 {
+        match *self {
+            Number::Byte(address) => memory[address as usize] as u32,
+            Number::Bcd(address, digits) => {
+                let start = address as usize;
+                memory[start..start + digits]
+                    .iter()
+                    .fold(0u32, |n, &digit| n.wrapping_mul(10).wrapping_add(digit as u32))
+            }
+        }
+    }
+
+    fn check(&self, name: &str) -> Result<(), String> {
+        let (address, length) = match *self {
+            Number::Byte(address) => (address as usize, 1),
+            Number::Bcd(address, digits) => {
+                if digits == 0 || digits > MAX_DIGITS {
+                    return Err(format!("{} has to have 1 to {} digits", name, MAX_DIGITS));
+                }
+                (address as usize, digits)
+            }
+        };
+        if address + length > MEMORY_SIZE {
+            return Err(format!("{} at {:03X} goes past the end of memory", name, address));
+        }
+
+        Ok(())
+    }
+}
+
+// What an agent can do in a game and what it is rewarded for
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules {
+    // Keys held down for each action. Action 0 is usually doing nothing.
+    pub actions: Vec<Vec<usize>>,
+    // Every point the score goes up is a reward of 1
+    pub score: Option<Number>,
+    // The episode ends when the lives reach zero
+    pub lives: Option<Number>,
+    // The episode ends when the program jumps to itself, which is how
+    // most CHIP-8 games stop once the game is over
+    pub end_on_halt: bool,
+}
+
+// Any single key or none at all, without rewards
+impl Default for Rules {
+    fn default() -> Rules {
+        let mut actions = vec![vec![]];
+        actions.extend((0..16).map(|key| vec![key]));
+
+        Rules {
+            actions,
+            score: None,
+            lives: None,
+            end_on_halt: true,
+        }
+    }
+}
+
+impl Rules {
+    // Every action holds existing keys, and the numbers are in memory
+    pub fn check(&self) -> Result<(), String> {
+        if self.actions.is_empty() {
+            return Err("there has to be at least one action".to_string());
+        }
+        for (action, keys) in self.actions.iter().enumerate() {
+            if let Some(key) = keys.iter().find(|&&key| key > 0xF) {
+                return Err(format!("action {} holds key {}, keys go from 0 to 15", action, key));
+            }
+        }
+        if let Some(score) = self.score {
+            score.check("score")?;
+        }
+        if let Some(lives) = self.lives {
+            lives.check("lives")?;
+        }
+
+        Ok(())
+    }
+}
+
+// The bundled rules of a game in roms/
+pub fn rules(name: &str) -> Option<Rules> {
+    BUNDLED.get_or_init(|| parse_rules(BUNDLED_RULES).expect("data/gym.json is invalid"))
+        .get(name)
+        .cloned()
+}
+
+// Rules for other games, from a file in the format of data/gym.json
+pub fn read_rules<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Rules>, String> {
+    let path = path.as_ref();
+    let mut json = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut json))
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+    parse_rules(&json).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Every game's rules are checked, like Environment::new does
+pub fn parse_rules(json: &str) -> Result<HashMap<String, Rules>, String> {
+    let games = match serde_json::from_str::<Value>(json).map_err(|e| e.to_string())? {
+        Value::Object(games) => games,
+        _ => return Err("the rules have to be an object".to_string()),
+    };
+
+    let mut rules = HashMap::new();
+    for (name, game) in games {
+        let game = game.as_object()
+            .ok_or_else(|| format!("{} has to be an object", name))
+            .and_then(parse_game)
+            .map_err(|e| format!("{}: {}", name, e))?;
+        game.check().map_err(|e| format!("{}: {}", name, e))?;
+        rules.insert(name, game);
+    }
+
+    Ok(rules)
+}
+
+fn parse_game(game: &Map<String, Value>) -> Result<Rules, String> {
+    let mut rules = Rules::default();
+    for (field, value) in game {
+        match field.as_str() {
+            "actions" => rules.actions = parse_actions(value)?,
+            "score" => rules.score = Some(parse_number(value).map_err(|e| format!("score {}", e))?),
+            "lives" => rules.lives = Some(parse_number(value).map_err(|e| format!("lives {}", e))?),
+            "endOnHalt" => {
+                rules.end_on_halt = value.as_bool()
+                    .ok_or_else(|| "endOnHalt has to be true or false".to_string())?
+            }
+            _ => return Err(format!("unknown field {}", field)),
+        }
+    }
+
+    Ok(rules)
+}
+
+fn parse_actions(value: &Value) -> Result<Vec<Vec<usize>>, String> {
+    let invalid = || "actions have to be lists of keys".to_string();
+    let actions = value.as_array().ok_or_else(invalid)?;
+
+    actions.iter()
+        .map(|keys| {
+            let keys = keys.as_array().ok_or_else(invalid)?;
+            keys.iter()
+                .map(|key| key.as_u64().map(|key| key as usize).ok_or_else(invalid))
+                .collect()
+        })
+        .collect()
+}
+
+fn parse_number(value: &Value) -> Result<Number, String> {
+    let address = value.get("address")
+        .and_then(Value::as_str)
+        .map(|address| address.trim_start_matches("0x"))
+        .and_then(|address| u16::from_str_radix(address, 16).ok())
+        .ok_or_else(|| "has to have a hex address".to_string())?;
+
+    match value.get("digits") {
+        None => Ok(Number::Byte(address)),
+        Some(digits) => {
+            digits.as_u64()
+                .map(|digits| Number::Bcd(address, digits as usize))
+                .ok_or_else(|| "digits have to be a number".to_string())
+        }
+    }
+}
+
+// How a step went, apart from the observation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Info {
+    // Frames played since the episode started
+    pub frame: u64,
+    pub score: Option<u32>,
+    pub lives: Option<u32>,
+    // Set when the program stopped with a fault, which also ends the episode
+    pub fault: Option<Fault>,
+}
+
+pub struct Step {
+    pub observation: Vec<u8>,
+    pub reward: f32,
+    pub done: bool,
+    pub info: Info,
+}
+
+pub struct Environment {
+    // Set the machine up (quirks, random seed) before the first reset
+    pub cpu: Chip8,
+    pub cycles_per_frame: usize,
+    // Frames played per step, with the same keys held down
+    pub frame_skip: usize,
+    rules: Rules,
+    score: u32,
+    frame: u64,
+    done: bool,
+    fault: Option<Fault>,
+}
+
+impl Environment {
+    // Fails if the rules don't fit the machine, see Rules::check
+    pub fn new(rom: &[u8], rules: Rules) -> Result<Environment, String> {
+        rules.check()?;
+        let mut cpu = Chip8::new();
+        cpu.load_rom(rom);
+
+        Ok(Environment {
+            cpu,
+            cycles_per_frame: CYCLES_PER_FRAME,
+            frame_skip: FRAME_SKIP,
+            rules,
+            score: 0,
+            frame: 0,
+            done: false,
+            fault: None,
+        })
+    }
+
+    pub fn action_count(&self) -> usize {
+        self.rules.actions.len()
+    }
+
+    // Start a new episode with the program restarted and no keys held down
+    pub fn reset(&mut self) -> Vec<u8> {
+        self.cpu.reset();
+        for key in 0..16 {
+            self.cpu.key_up(key);
+        }
+        self.score = self.read(self.rules.score).unwrap_or(0);
+        self.frame = 0;
+        self.done = false;
+        self.fault = None;
+
+        self.observation()
+    }
+
+    // Play `frame_skip` frames with the keys of `action` held down.
+    // Once the episode is done, steps don't change anything until the next reset.
+    // Panics if there is no such action.
+    pub fn step(&mut self, action: usize) -> Step {
+        let keys = &self.rules.actions[action];
+        if !self.done {
+            for key in 0..16 {
+                if keys.contains(&key) {
+                    self.cpu.key_down(key);
+                } else {
+                    self.cpu.key_up(key);
+                }
+            }
+
+            for _ in 0..self.frame_skip {
+                if let Err(fault) = self.cpu.run_frame(self.cycles_per_frame) {
+                    self.fault = Some(fault);
+                    break;
+                }
+                self.frame += 1;
+            }
+        }
+
+        let score = self.read(self.rules.score);
+        let lives = self.read(self.rules.lives);
+        let reward = score.map_or(0.0, |score| score as f32 - self.score as f32);
+        self.score = score.unwrap_or(0);
+        self.done = self.done ||
+                    self.fault.is_some() ||
+                    lives == Some(0) ||
+                    (self.rules.end_on_halt && self.halted());
+
+        Step {
+            observation: self.observation(),
+            reward,
+            done: self.done,
+            info: Info {
+                frame: self.frame,
+                score,
+                lives,
+                fault: self.fault,
+            },
+        }
+    }
+
+    fn observation(&self) -> Vec<u8> {
+        self.cpu.gfx.to_bytes()
+    }
+
+    fn read(&self, number: Option<Number>) -> Option<u32> {
+        number.map(|number| number.read(self.cpu.memory()))
+    }
+
+    // The next instruction is a jump to itself
+    fn halted(&self) -> bool {
+        let pc = self.cpu.pc() as usize % MEMORY_SIZE;
+        let memory = self.cpu.memory();
+        let opcode = (memory[pc] as u16) << 8 | memory[(pc + 1) % MEMORY_SIZE] as u16;
+
+        opcode == 0x1000 | self.cpu.pc()
+    }
+}
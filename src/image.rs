@@ -0,0 +1,49 @@
+use std::io::{self, Write};
+use png;
+
+use palette::Palette;
+
+// Enlarge a framebuffer, every emulated pixel becoming a `scale` x `scale` block.
+// Pixel values are kept as they are, so they can be used as palette indices.
+pub fn scale(gfx: &[u8], resolution: (usize, usize), scale: usize) -> Vec<u8> {
+    let (width, height) = resolution;
+    let mut pixels = Vec::with_capacity(width * height * scale * scale);
+
+    for y in 0..height * scale {
+        for x in 0..width * scale {
+            pixels.push(gfx[(y / scale) * width + (x / scale)]);
+        }
+    }
+
+    pixels
+}
+
+// Expand a framebuffer into RGB bytes in the colours of `palette`
+pub fn to_rgb(gfx: &[u8], resolution: (usize, usize), scale: usize, palette: &Palette) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(resolution.0 * resolution.1 * scale * scale * 3);
+
+    for pixel in self::scale(gfx, resolution, scale) {
+        let (r, g, b) = palette.colors[(pixel as usize).min(3)];
+        rgb.extend_from_slice(&[r, g, b]);
+    }
+
+    rgb
+}
+
+// Encode a framebuffer as a PNG image
+pub fn write_png<W: Write>(writer: W,
+                           gfx: &[u8],
+                           resolution: (usize, usize),
+                           scale: usize,
+                           palette: &Palette)
+                           -> io::Result<()> {
+    let (width, height) = resolution;
+    let mut encoder = png::Encoder::new(writer, (width * scale) as u32, (height * scale) as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut png = encoder.write_header()?;
+    png.write_image_data(&to_rgb(gfx, resolution, scale, palette))?;
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+
+// A host input that can hold a CHIP-8 key down. Controllers are told apart by their instance id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(Keycode),
+    Button(i32, Button),
+    // The left stick pushed towards a D-pad direction
+    Stick(i32, Button),
+}
+
+// Several inputs can be bound to one CHIP-8 key, which stays down
+// until the last of the inputs holding it is released
+#[derive(Default)]
+pub struct HeldKeys {
+    held: HashMap<Binding, usize>,
+}
+
+impl HeldKeys {
+    pub fn new() -> HeldKeys {
+        HeldKeys::default()
+    }
+
+    // Returns whether `key` went down, i.e. wasn't held by another input already
+    pub fn press(&mut self, binding: Binding, key: usize) -> bool {
+        let was_up = !self.is_down(key);
+        self.held.insert(binding, key);
+
+        was_up
+    }
+
+    // Returns the CHIP-8 key that went up, if `binding` was the last input holding it
+    pub fn release(&mut self, binding: Binding) -> Option<usize> {
+        match self.held.remove(&binding) {
+            Some(key) if !self.is_down(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    // Release everything a disconnected controller held,
+    // returning the CHIP-8 keys that went up
+    pub fn release_controller(&mut self, controller: i32) -> Vec<usize> {
+        let bindings: Vec<Binding> = self.held
+            .keys()
+            .filter(|binding| match **binding {
+                Binding::Button(id, _) | Binding::Stick(id, _) => id == controller,
+                Binding::Key(_) => false,
+            })
+            .cloned()
+            .collect();
+
+        bindings.into_iter().filter_map(|binding| self.release(binding)).collect()
+    }
+
+    fn is_down(&self, key: usize) -> bool {
+        self.held.values().any(|&k| k == key)
+    }
+}
@@ -0,0 +1,143 @@
+// CHIP-8 instructions, decoded from their opcodes.
+// X and Y are register numbers, N a 4-bit number, NN a byte and NNN an address.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    // 00E0
+    ClearScreen,
+    // 00EE
+    Return,
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipIfEqual(u8, u8),
+    // 4XNN
+    SkipIfNotEqual(u8, u8),
+    // 5XY0
+    SkipIfRegistersEqual(u8, u8),
+    // 6XNN
+    Set(u8, u8),
+    // 7XNN
+    Add(u8, u8),
+    // 8XY0
+    Copy(u8, u8),
+    // 8XY1
+    Or(u8, u8),
+    // 8XY2
+    And(u8, u8),
+    // 8XY3
+    Xor(u8, u8),
+    // 8XY4
+    AddRegisters(u8, u8),
+    // 8XY5
+    Subtract(u8, u8),
+    // 8XY6
+    ShiftRight(u8, u8),
+    // 8XY7
+    SubtractFrom(u8, u8),
+    // 8XYE
+    ShiftLeft(u8, u8),
+    // 9XY0
+    SkipIfRegistersNotEqual(u8, u8),
+    // ANNN
+    SetIndex(u16),
+    // BNNN, X is used instead of V0 with the jump quirk
+    JumpWithOffset(u16, u8),
+    // CXNN
+    Random(u8, u8),
+    // DXYN
+    Draw(u8, u8, u8),
+    // EX9E
+    SkipIfKey(u8),
+    // EXA1
+    SkipIfNotKey(u8),
+    // FX07
+    GetDelay(u8),
+    // FX0A
+    WaitForKey(u8),
+    // FX15
+    SetDelay(u8),
+    // FX18
+    SetSound(u8),
+    // FX1E
+    AddToIndex(u8),
+    // FX29
+    Digit(u8),
+    // FX33
+    Bcd(u8),
+    // FX55
+    Store(u8),
+    // FX65
+    Load(u8),
+    Unknown(u16),
+}
+
+// Like chipper always did, only the bits that tell instructions apart are looked at:
+// any 0NN0 clears the screen and any 0NNE returns.
+pub fn decode(opcode: u16) -> Instruction {
+    use self::Instruction::*;
+
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let address = opcode & 0x0FFF;
+
+    match opcode & 0xF000 {
+        0x0000 => {
+            match n {
+                0x0 => ClearScreen,
+                0xE => Return,
+                _ => Unknown(opcode),
+            }
+        }
+        0x1000 => Jump(address),
+        0x2000 => Call(address),
+        0x3000 => SkipIfEqual(x, nn),
+        0x4000 => SkipIfNotEqual(x, nn),
+        0x5000 => SkipIfRegistersEqual(x, y),
+        0x6000 => Set(x, nn),
+        0x7000 => Add(x, nn),
+        0x8000 => {
+            match n {
+                0x0 => Copy(x, y),
+                0x1 => Or(x, y),
+                0x2 => And(x, y),
+                0x3 => Xor(x, y),
+                0x4 => AddRegisters(x, y),
+                0x5 => Subtract(x, y),
+                0x6 => ShiftRight(x, y),
+                0x7 => SubtractFrom(x, y),
+                0xE => ShiftLeft(x, y),
+                _ => Unknown(opcode),
+            }
+        }
+        0x9000 => SkipIfRegistersNotEqual(x, y),
+        0xA000 => SetIndex(address),
+        0xB000 => JumpWithOffset(address, x),
+        0xC000 => Random(x, nn),
+        0xD000 => Draw(x, y, n),
+        0xE000 => {
+            match nn {
+                0x9E => SkipIfKey(x),
+                0xA1 => SkipIfNotKey(x),
+                _ => Unknown(opcode),
+            }
+        }
+        _ => {
+            match nn {
+                0x07 => GetDelay(x),
+                0x0A => WaitForKey(x),
+                0x15 => SetDelay(x),
+                0x18 => SetSound(x),
+                0x1E => AddToIndex(x),
+                0x29 => Digit(x),
+                0x33 => Bcd(x),
+                0x55 => Store(x),
+                0x65 => Load(x),
+                _ => Unknown(opcode),
+            }
+        }
+    }
+}
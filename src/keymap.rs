@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, HashMap};
+use sdl2::keyboard::Keycode;
+use toml::Value;
+
+use config::{self, Names};
+
+// CHIP-8 keypad layout
+//
+// +---+---+---+---+
+// | 1 | 2 | 3 | C |
+// +---+---+---+---+
+// | 4 | 5 | 6 | D |
+// +---+---+---+---+
+// | 7 | 8 | 9 | E |
+// +---+---+---+---+
+// | A | 0 | B | F |
+// +---+---+---+---+
+//
+// The QWERTY and AZERTY presets keep the shape of the keypad on the left side of the keyboard.
+// SDL always reports the number row as digits, so AZERTY only differs in the letter rows.
+const QWERTY: [(Keycode, usize); 16] = [
+    (Keycode::Num1, 0x1), (Keycode::Num2, 0x2), (Keycode::Num3, 0x3), (Keycode::Num4, 0xC),
+    (Keycode::Q, 0x4), (Keycode::W, 0x5), (Keycode::E, 0x6), (Keycode::R, 0xD),
+    (Keycode::A, 0x7), (Keycode::S, 0x8), (Keycode::D, 0x9), (Keycode::F, 0xE),
+    (Keycode::Z, 0xA), (Keycode::X, 0x0), (Keycode::C, 0xB), (Keycode::V, 0xF),
+];
+
+const AZERTY: [(Keycode, usize); 16] = [
+    (Keycode::Num1, 0x1), (Keycode::Num2, 0x2), (Keycode::Num3, 0x3), (Keycode::Num4, 0xC),
+    (Keycode::A, 0x4), (Keycode::Z, 0x5), (Keycode::E, 0x6), (Keycode::R, 0xD),
+    (Keycode::Q, 0x7), (Keycode::S, 0x8), (Keycode::D, 0x9), (Keycode::F, 0xE),
+    (Keycode::W, 0xA), (Keycode::X, 0x0), (Keycode::C, 0xB), (Keycode::V, 0xF),
+];
+
+// Numpad digits map to the same CHIP-8 digits, the remaining keys fill in A-F
+const NUMPAD: [(Keycode, usize); 16] = [
+    (Keycode::Kp0, 0x0), (Keycode::Kp1, 0x1), (Keycode::Kp2, 0x2), (Keycode::Kp3, 0x3),
+    (Keycode::Kp4, 0x4), (Keycode::Kp5, 0x5), (Keycode::Kp6, 0x6), (Keycode::Kp7, 0x7),
+    (Keycode::Kp8, 0x8), (Keycode::Kp9, 0x9), (Keycode::KpDivide, 0xA),
+    (Keycode::KpMultiply, 0xB), (Keycode::KpMinus, 0xC), (Keycode::KpPlus, 0xD),
+    (Keycode::KpEnter, 0xE), (Keycode::KpPeriod, 0xF),
+];
+
+// Keys for what the ROM database says a game uses its keys for, on top of the keypad
+const ACTIONS: [(&str, Keycode); 6] = [
+    ("up", Keycode::Up),
+    ("down", Keycode::Down),
+    ("left", Keycode::Left),
+    ("right", Keycode::Right),
+    ("a", Keycode::Space),
+    ("b", Keycode::LShift),
+];
+
+pub const PRESETS: [&str; 3] = ["qwerty", "azerty", "numpad"];
+
+// Keymap sections look like this (every entry is optional):
+//
+// [keymap]
+// preset = "azerty"
+//
+// [keymap.keys]
+// 5 = ["W", "Up"]
+// 8 = "S"
+//
+// Listing a CHIP-8 key replaces all of its bindings from the preset or a previous file.
+#[derive(Deserialize)]
+struct KeymapConfig {
+    preset: Option<String>,
+    #[serde(default)]
+    keys: HashMap<String, Names>,
+}
+
+pub struct Keymap {
+    keys: HashMap<Keycode, usize>,
+}
+
+impl Keymap {
+    pub fn preset(name: &str) -> Result<Keymap, String> {
+        let layout = match name.to_lowercase().as_str() {
+            "qwerty" => QWERTY,
+            "azerty" => AZERTY,
+            "numpad" => NUMPAD,
+            _ => {
+                return Err(format!("Unknown keymap preset \"{}\" (available: {})",
+                                   name,
+                                   PRESETS.join(", ")))
+            }
+        };
+
+        Ok(Keymap { keys: layout.iter().cloned().collect() })
+    }
+
+    // Build the keymap for a ROM: QWERTY, then the global config, then the ROM's own config.
+    // A preset given on the command line wins over all of them.
+    pub fn load(rom: &str, preset: Option<&str>) -> Result<Keymap, String> {
+        if let Some(name) = preset {
+            return Keymap::preset(name);
+        }
+
+        let mut keymap = Keymap::preset("qwerty")?;
+        for path in config::global_config().into_iter().chain(config::rom_config(rom)) {
+            if let Some(section) = config::read_section(&path, "keymap")? {
+                keymap.apply(section).map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+        }
+
+        Ok(keymap)
+    }
+
+    fn apply(&mut self, section: Value) -> Result<(), String> {
+        let settings: KeymapConfig = section.try_into().map_err(|e| e.to_string())?;
+
+        if let Some(name) = settings.preset {
+            self.keys = Keymap::preset(&name)?.keys;
+        }
+
+        for (chip_key, names) in settings.keys {
+            let key = config::parse_chip_key(&chip_key)?;
+
+            self.keys.retain(|_, k| *k != key);
+            for name in names.into_vec() {
+                let keycode = Keycode::from_name(&name)
+                    .ok_or_else(|| format!("Unknown key name \"{}\"", name))?;
+                self.keys.insert(keycode, key);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Bind the arrow keys, Space and Shift to the CHIP-8 keys of a game's actions
+    // (e.g. "left" -> 0x4). Keys already bound in a config file are left alone.
+    pub fn bind_actions(&mut self, actions: &BTreeMap<String, u8>) {
+        for &(action, keycode) in ACTIONS.iter() {
+            if let Some(&key) = actions.get(action) {
+                self.keys.entry(keycode).or_insert(key as usize);
+            }
+        }
+    }
+
+    pub fn get(&self, keycode: Keycode) -> Option<usize> {
+        self.keys.get(&keycode).cloned()
+    }
+
+    // Names of the keyboard keys bound to a CHIP-8 key
+    pub fn names(&self, key: usize) -> Vec<String> {
+        let mut names: Vec<String> = self.keys
+            .iter()
+            .filter(|&(_, &k)| k == key)
+            .map(|(keycode, _)| keycode.name())
+            .collect();
+        names.sort();
+
+        names
+    }
+}
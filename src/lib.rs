@@ -0,0 +1,26 @@
+// The emulator core: the CHIP-8 interpreter and what it needs to turn its screen into images
+// and its buzzer into sound.
+// It doesn't depend on SDL, so it can be tested and embedded on its own.
+extern crate rand;
+extern crate png;
+extern crate gif;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate sha1;
+
+pub mod buzzer;
+pub mod cheats;
+pub mod cpu;
+pub mod font;
+pub mod framebuffer;
+pub mod gym;
+pub mod image;
+pub mod instruction;
+pub mod netplay;
+pub mod palette;
+pub mod recording;
+pub mod remote;
+pub mod romdb;
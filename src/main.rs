@@ -1,19 +1,75 @@
 extern crate sdl2;
-extern crate rand;
 extern crate clap;
 extern crate rodio;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+extern crate hound;
+extern crate serde_json;
+extern crate chipper;
 
-mod cpu;
-mod font;
+mod audio;
+mod config;
+mod console;
+mod database;
+mod display;
+mod game;
+mod gamepad;
+mod input;
+mod keymap;
+mod menu;
+mod osd;
+mod phosphor;
+mod speaker;
+mod text;
 
-use std::time::Instant;
-use sdl2::event::Event;
-use sdl2::rect::Rect;
-use sdl2::pixels::Color;
-use sdl2::keyboard::Keycode;
+use std::mem;
+use std::net::TcpListener;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use sdl2::controller::Button;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, LALTMOD, RALTMOD};
+use sdl2::video::{FullscreenType, Window};
 use clap::{App, Arg};
+use chipper::{buzzer, cheats, cpu, framebuffer, netplay, palette, recording, remote, romdb};
 
-use cpu::Chip8;
+use console::Console;
+use cpu::{Chip8, SCREEN_WIDTH, SCREEN_HEIGHT};
+use display::color;
+use game::Game;
+use input::Binding;
+use keymap::PRESETS;
+use menu::Menu;
+use netplay::Netplay;
+use osd::Osd;
+use palette::Palette;
+use phosphor::Phosphor;
+use speaker::Speaker;
+
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// Emulation speeds, as a multiple of 60 frames per second
+const SPEEDS: [f32; 7] = [0.25, 0.5, 1.0, 1.5, 2.0, 4.0, 8.0];
+const NORMAL_SPEED: usize = 2;
+
+// Hotkeys that would make the two machines run different frames during netplay:
+// pausing, stepping, resetting and changing the speed
+const NETPLAY_BLOCKED_KEYS: [Keycode; 8] = [Keycode::F5,
+                                            Keycode::Pause,
+                                            Keycode::F6,
+                                            Keycode::F7,
+                                            Keycode::F8,
+                                            Keycode::Minus,
+                                            Keycode::Equals,
+                                            Keycode::Tab];
+
+// The window shows either the ROM browser or a running game
+enum Screen {
+    Menu(Menu),
+    Game(Box<Game>),
+}
 
 fn main() {
     let matches = App::new("Chipper")
@@ -21,120 +77,723 @@ fn main() {
         .author("Mateusz Mrowiec <matt.mrowiec@gmail.com>")
         .about("Chip8 emulator/interpreter")
         .arg(Arg::with_name("INPUT")
-            .help("Sets the input file to use")
-            .required(true)
+            .help("Sets the input file to use, without it a list of ROMs is shown")
             .index(1))
+        .arg(Arg::with_name("rom-dir")
+            .long("rom-dir")
+            .value_name("DIR")
+            .help("Sets the directory the ROM list is read from (default: roms)")
+            .takes_value(true))
+        .arg(Arg::with_name("keymap")
+            .long("keymap")
+            .value_name("PRESET")
+            .help("Overrides the configured keymap with a preset")
+            .possible_values(&PRESETS)
+            .takes_value(true))
+        .arg(Arg::with_name("theme")
+            .long("theme")
+            .value_name("THEME")
+            .help("Sets the colour theme (F2 cycles through themes while running)")
+            .possible_values(&palette::theme_names())
+            .default_value("classic"))
+        .arg(Arg::with_name("fg")
+            .long("fg")
+            .value_name("RRGGBB")
+            .help("Overrides the pixel colour of the theme")
+            .takes_value(true))
+        .arg(Arg::with_name("bg")
+            .long("bg")
+            .value_name("RRGGBB")
+            .help("Overrides the background colour of the theme")
+            .takes_value(true))
+        .arg(Arg::with_name("scale")
+            .long("scale")
+            .value_name("N")
+            .help("Sets the initial window size to N window pixels per CHIP-8 pixel")
+            .default_value("20"))
+        .arg(Arg::with_name("fullscreen")
+            .long("fullscreen")
+            .help("Starts in fullscreen mode (F11 or Alt+Enter toggles it)"))
+        .arg(Arg::with_name("phosphor")
+            .long("phosphor")
+            .value_name("DECAY")
+            .help("Fades pixels out like a CRT instead of turning them off instantly. \
+                   DECAY (0.0 to below 1.0) is the part of brightness kept each frame, e.g. 0.6")
+            .takes_value(true))
+        .arg(Arg::with_name("record-gif")
+            .long("record-gif")
+            .value_name("FILE")
+            .help("Records the screen into an animated GIF from the start (F9 toggles recording)")
+            .takes_value(true))
+        .arg(Arg::with_name("gif-scale")
+            .long("gif-scale")
+            .value_name("N")
+            .help("Sets the size of GIF recordings to N pixels per CHIP-8 pixel")
+            .default_value("4"))
+        .arg(Arg::with_name("record-audio")
+            .long("record-audio")
+            .value_name("FILE")
+            .help("Records the buzzer into a WAV file")
+            .takes_value(true))
+        .arg(Arg::with_name("headless")
+            .long("headless")
+            .help("Runs without a window or sound for the number of frames given by --frames")
+            .requires_all(&["frames", "INPUT"]))
+        .arg(Arg::with_name("frames")
+            .long("frames")
+            .value_name("N")
+            .help("Sets how many frames (1/60 s) to emulate in headless mode")
+            .takes_value(true))
+        .arg(Arg::with_name("remote")
+            .long("remote")
+            .value_name("ADDRESS")
+            .help("Runs without a window, controlled with JSON-RPC on a loopback host:port \
+                   or a Unix socket path")
+            .takes_value(true))
+        .arg(Arg::with_name("netplay-host")
+            .long("netplay-host")
+            .value_name("ADDRESS")
+            .help("Waits for a second player on ADDRESS (host:port) to play together")
+            .takes_value(true)
+            .requires("INPUT")
+            .conflicts_with_all(&["netplay-join", "headless", "remote"]))
+        .arg(Arg::with_name("netplay-join")
+            .long("netplay-join")
+            .value_name("ADDRESS")
+            .help("Joins the player waiting on ADDRESS (host:port) to play together")
+            .takes_value(true)
+            .requires("INPUT")
+            .conflicts_with_all(&["headless", "remote"]))
+        .arg(Arg::with_name("netplay-delay")
+            .long("netplay-delay")
+            .value_name("FRAMES")
+            .help("Sets how many frames ahead keys are sent when hosting netplay")
+            .default_value("2"))
+        .arg(Arg::with_name("console")
+            .long("console")
+            .help("Reads commands from the terminal while playing, for searching memory \
+                   and freezing values (type help for the commands)")
+            .conflicts_with_all(&["headless", "remote", "netplay-host", "netplay-join"]))
         .get_matches();
 
-    let input_file = matches.value_of("INPUT").unwrap();
-    println!("Using input file: {}", input_file);
+    let keymap_preset = matches.value_of("keymap");
+
+    let mut palettes = palette::themes();
+    let mut current_palette = palettes.iter()
+        .position(|p| p.name == matches.value_of("theme").unwrap())
+        .unwrap();
+    for (arg, index) in [("bg", 0), ("fg", 1)].iter() {
+        if let Some(value) = matches.value_of(arg) {
+            match palette::parse_color(value) {
+                Ok(rgb) => palettes[current_palette].colors[*index] = rgb,
+                Err(e) => {
+                    println!("Invalid --{} colour: {}", arg, e);
+                    return;
+                }
+            }
+        }
+    }
+
+    let scale = match matches.value_of("scale").unwrap().parse::<u32>() {
+        Ok(scale) if scale > 0 => scale,
+        _ => {
+            println!("--scale has to be a positive number");
+            return;
+        }
+    };
+
+    let phosphor_decay = match matches.value_of("phosphor").map(|decay| decay.parse::<f32>()) {
+        None => None,
+        // At 1.0 erased pixels would never fade out
+        Some(Ok(decay)) if (0.0..1.0).contains(&decay) => Some(decay),
+        Some(_) => {
+            println!("--phosphor has to be a number from 0.0 up to (not including) 1.0");
+            return;
+        }
+    };
+
+    let gif_scale = match matches.value_of("gif-scale").unwrap().parse::<usize>() {
+        Ok(scale) if scale > 0 => scale,
+        _ => {
+            println!("--gif-scale has to be a positive number");
+            return;
+        }
+    };
+
+    // The recordings asked for on the command line cover the first game that is played
+    let mut record_gif = matches.value_of("record-gif");
+    let mut record_audio = matches.value_of("record-audio");
+
+    let mut game = None;
+    if let Some(input_file) = matches.value_of("INPUT") {
+        match launch(input_file,
+                     keymap_preset,
+                     record_gif.take(),
+                     record_audio.take(),
+                     gif_scale,
+                     &palettes[current_palette]) {
+            Ok(loaded) => game = Some(loaded),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+
+    if matches.is_present("headless") {
+        let frames = match matches.value_of("frames").unwrap().parse::<u64>() {
+            Ok(frames) => frames,
+            Err(_) => {
+                println!("--frames has to be a number");
+                return;
+            }
+        };
+
+        // INPUT is required with --headless, so the game has been loaded
+        let mut game = game.unwrap();
+        for _ in 0..frames {
+            if let Err(e) = game.frame(&palettes[current_palette]) {
+                println!("{}", e);
+            }
+        }
+
+        return;
+    }
+
+    if let Some(address) = matches.value_of("remote") {
+        let (cpu, cycles_per_frame) = match game {
+            Some(mut game) => (mem::take(&mut game.cpu), game.cycles_per_frame),
+            None => (Chip8::new(), cpu::CYCLES_PER_FRAME),
+        };
+        let mut session = remote::Session::new(cpu, cycles_per_frame, load_remote);
+        if let Err(e) = remote::serve(address, &mut session, |message| println!("{}", message)) {
+            println!("Remote control stopped: {}", e);
+        }
+
+        return;
+    }
+
+    // Both players start the game afresh once they are connected
+    let mut netplay = None;
+    if let Some(address) = matches.value_of("netplay-host") {
+        let delay = match matches.value_of("netplay-delay").unwrap().parse::<usize>() {
+            Ok(delay) => delay,
+            Err(_) => {
+                println!("--netplay-delay has to be a number");
+                return;
+            }
+        };
+        let listener = match TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(e) => {
+                println!("Could not wait for the other player on {}: {}", address, e);
+                return;
+            }
+        };
+        println!("Waiting for the other player on {}", address);
+        match netplay::host(&listener, &mut game.as_mut().unwrap().cpu, delay) {
+            Ok(session) => netplay = Some(session),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+    if let Some(address) = matches.value_of("netplay-join") {
+        match netplay::join(address, &mut game.as_mut().unwrap().cpu) {
+            Ok(session) => netplay = Some(session),
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
+    }
+    // Frozen addresses only change this side's memory, the games would go out of sync at once
+    if netplay.is_some() {
+        let game = game.as_mut().unwrap();
+        if !game.cheats.is_empty() {
+            println!("Cheats are off during netplay");
+            game.cheats = cheats::Cheats::new();
+        }
+    }
+
+    let rom_dir = match menu::rom_dir(matches.value_of("rom-dir")) {
+        Ok(dir) => dir,
+        Err(e) => {
+            println!("Could not read the launcher settings: {}", e);
+            return;
+        }
+    };
+
+    let mut screen = match game {
+        Some(game) => Screen::Game(Box::new(game)),
+        None => {
+            match Menu::scan(&rom_dir) {
+                Ok(menu) => Screen::Menu(menu),
+                Err(e) => {
+                    println!("Could not list ROMs: {}", e);
+                    return;
+                }
+            }
+        }
+    };
+    let mut phosphor = phosphor_decay.map(Phosphor::new);
 
     // Set up render system and register input callbacks
     let context = sdl2::init().unwrap();
     let video = context.video().unwrap();
 
-    let window = video.window("Chipper", 1280, 640).position_centered().build().unwrap();
+    let mut window = video.window("Chipper",
+                                  SCREEN_WIDTH as u32 * scale,
+                                  SCREEN_HEIGHT as u32 * scale)
+        .position_centered()
+        .resizable()
+        .build()
+        .unwrap();
+    window.set_minimum_size(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32).unwrap();
+    if matches.is_present("fullscreen") {
+        window.set_fullscreen(FullscreenType::Desktop).unwrap();
+    }
 
     let mut canvas = window.into_canvas().build().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut screen_texture = display::ScreenTexture::new(&texture_creator);
 
-    canvas.set_draw_color(Color::RGB(40, 40, 40));
+    canvas.set_draw_color(color(palettes[current_palette].background()));
     canvas.clear();
     canvas.present();
 
     let mut events = context.event_pump().unwrap();
 
-    // Initialize the Chip8 system and load the game into the memory
-    let mut cpu = Chip8::new();
-    cpu.load_game(input_file);
+    // Controllers are opened as they are connected (SDL also reports the ones present at startup)
+    // and have to be kept alive to keep sending events
+    let controller_system = context.game_controller().unwrap();
+    let mut controllers = Vec::new();
+
+    let speaker = Speaker::new();
+    if speaker.is_none() {
+        println!("No audio device found, the buzzer is muted");
+    }
+
+    let mut osd = Osd::new();
+    if let Screen::Game(ref game) = screen {
+        osd.info(&format!("Loaded {}", game.title));
+    }
+
+    let console = if matches.is_present("console") {
+        Some(Console::start())
+    } else {
+        None
+    };
 
     let mut last_frame = Instant::now();
+    let mut redraw = true;
+
+    // Emulation controls
+    let mut paused = false;
+    let mut speed = NORMAL_SPEED;
+    let mut fast_forward = false;
+    // Frames owed to the emulation at speeds that aren't whole numbers
+    let mut pending_frames = 0.0;
+    // Frames to run while paused, one per press of the frame advance key
+    let mut advance_frames = 0;
 
     // Emulation loop
     'game: loop {
+        // Switching between the menu and a game happens after the events are handled
+        let mut play = None;
+        let mut back_to_menu = false;
+
         // Store key press state (Press and Release)
         for event in events.poll_iter() {
+            // Hotkeys that work everywhere
             match event {
                 Event::Quit { .. } => break 'game,
-                Event::KeyDown { keycode: Some(keycode), .. } => {
-                    match keycode {
-                        Keycode::Escape => break 'game,
-
-                        // Keycodes correspond to keypad
-                        //
-                        // +---+---+---+---+
-                        // | 1 | 2 | 3 | C |
-                        // +---+---+---+---+
-                        // | 4 | 5 | 6 | D |
-                        // +---+---+---+---+
-                        // | 7 | 8 | 9 | E |
-                        // +---+---+---+---+
-                        // | A | 0 | B | F |
-                        // +---+---+---+---+
-                        Keycode::Num1 => cpu.key[0x1] = 1,
-                        Keycode::Num2 => cpu.key[0x2] = 1,
-                        Keycode::Num3 => cpu.key[0x3] = 1,
-                        Keycode::Num4 => cpu.key[0xC] = 1,
-                        Keycode::Q => cpu.key[0x4] = 1,
-                        Keycode::W => cpu.key[0x5] = 1,
-                        Keycode::E => cpu.key[0x6] = 1,
-                        Keycode::R => cpu.key[0xD] = 1,
-                        Keycode::A => cpu.key[0x7] = 1,
-                        Keycode::S => cpu.key[0x8] = 1,
-                        Keycode::D => cpu.key[0x9] = 1,
-                        Keycode::F => cpu.key[0xE] = 1,
-                        Keycode::Z => cpu.key[0xA] = 1,
-                        Keycode::X => cpu.key[0x0] = 1,
-                        Keycode::C => cpu.key[0xB] = 1,
-                        Keycode::V => cpu.key[0xF] = 1,
-                        _ => {}
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'game,
+                Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
+                    current_palette = (current_palette + 1) % palettes.len();
+                    osd.info(&format!("Theme: {}", palettes[current_palette].name));
+                    redraw = true;
+                    continue;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
+                    osd.show_stats = !osd.show_stats;
+                    redraw = true;
+                    continue;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F11), .. } => {
+                    toggle_fullscreen(canvas.window_mut());
+                    continue;
+                }
+                Event::KeyDown { keycode: Some(Keycode::Return), keymod, .. }
+                    if keymod.intersects(LALTMOD | RALTMOD) => {
+                    toggle_fullscreen(canvas.window_mut());
+                    continue;
+                }
+                // The whole window has to be repainted after a resize
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } |
+                Event::Window { win_event: WindowEvent::Exposed, .. } => {
+                    redraw = true;
+                    continue;
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match controller_system.open(which as u32) {
+                        Ok(controller) => {
+                            osd.info(&format!("Controller connected: {}", controller.name()));
+                            controllers.push(controller);
+                        }
+                        Err(e) => osd.error(&format!("Could not open controller: {}", e)),
                     }
+                    continue;
                 }
-                Event::KeyUp { keycode: Some(keycode), .. } => {
-                    match keycode {
-                        Keycode::Num1 => cpu.key[0x1] = 0,
-                        Keycode::Num2 => cpu.key[0x2] = 0,
-                        Keycode::Num3 => cpu.key[0x3] = 0,
-                        Keycode::Num4 => cpu.key[0xC] = 0,
-                        Keycode::Q => cpu.key[0x4] = 0,
-                        Keycode::W => cpu.key[0x5] = 0,
-                        Keycode::E => cpu.key[0x6] = 0,
-                        Keycode::R => cpu.key[0xD] = 0,
-                        Keycode::A => cpu.key[0x7] = 0,
-                        Keycode::S => cpu.key[0x8] = 0,
-                        Keycode::D => cpu.key[0x9] = 0,
-                        Keycode::F => cpu.key[0xE] = 0,
-                        Keycode::Z => cpu.key[0xA] = 0,
-                        Keycode::X => cpu.key[0x0] = 0,
-                        Keycode::C => cpu.key[0xB] = 0,
-                        Keycode::V => cpu.key[0xF] = 0,
-                        _ => {}
+                // Whatever the controller held is let go
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    controllers.retain(|c| c.instance_id() != which);
+                    if let Screen::Game(ref mut game) = screen {
+                        for key in game.held.release_controller(which) {
+                            press_key(game, &mut netplay, key, false);
+                        }
                     }
+                    continue;
                 }
                 _ => {}
             }
+
+            match screen {
+                Screen::Menu(ref mut menu) => {
+                    match event {
+                        Event::KeyDown { keycode: Some(Keycode::Up), .. } |
+                        Event::ControllerButtonDown { button: Button::DPadUp, .. } => {
+                            menu.select_previous()
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::Down), .. } |
+                        Event::ControllerButtonDown { button: Button::DPadDown, .. } => {
+                            menu.select_next()
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::PageUp), .. } => menu.page_up(),
+                        Event::KeyDown { keycode: Some(Keycode::PageDown), .. } => {
+                            menu.page_down()
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::Return), .. } |
+                        Event::ControllerButtonDown { button: Button::A, .. } |
+                        Event::ControllerButtonDown { button: Button::Start, .. } => {
+                            play = menu.selected().map(|entry| entry.path.clone());
+                        }
+                        _ => continue,
+                    }
+                    redraw = true;
+                }
+                Screen::Game(ref mut game) => {
+                    match event {
+                        // Both machines have to run the same frames
+                        Event::KeyDown { keycode: Some(keycode), .. }
+                            if netplay.is_some() && NETPLAY_BLOCKED_KEYS.contains(&keycode) => {
+                            osd.error("Not available during netplay");
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                            back_to_menu = true
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } |
+                        Event::KeyDown { keycode: Some(Keycode::Pause), repeat: false, .. } => {
+                            paused = !paused;
+                            osd.info(if paused { "Paused" } else { "Resumed" });
+                        }
+                        // Frame advance and instruction step pause the emulation first
+                        Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
+                            if paused {
+                                advance_frames += 1;
+                            } else {
+                                paused = true;
+                                osd.info("Paused");
+                            }
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                            if paused {
+                                let pc = game.cpu.pc();
+                                match game.step() {
+                                    Ok(_) => {
+                                        osd.info(&format!("{:03X}: {:04X}",
+                                                          pc,
+                                                          game.cpu.opcode()))
+                                    }
+                                    Err(e) => osd.error(&e),
+                                }
+                            } else {
+                                paused = true;
+                                osd.info("Paused");
+                            }
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::F8), repeat: false, .. } => {
+                            game.reset();
+                            phosphor = phosphor_decay.map(Phosphor::new);
+                            osd.info("Reset");
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::Minus), .. } => {
+                            speed = speed.saturating_sub(1);
+                            osd.info(&format!("Speed: {}%", SPEEDS[speed] * 100.0));
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::Equals), .. } => {
+                            speed = (speed + 1).min(SPEEDS.len() - 1);
+                            osd.info(&format!("Speed: {}%", SPEEDS[speed] * 100.0));
+                        }
+                        // Fast-forward lasts as long as the key is held
+                        Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, .. } => {
+                            fast_forward = true
+                        }
+                        Event::KeyUp { keycode: Some(Keycode::Tab), .. } => fast_forward = false,
+                        Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                            let path = game.output_path("png");
+                            match game.cpu.export_png(&path,
+                                                      scale as usize,
+                                                      &palettes[current_palette]) {
+                                Ok(_) => osd.info(&format!("Screenshot saved to {}", path)),
+                                Err(e) => osd.error(&format!("Could not save screenshot: {}", e)),
+                            }
+                        }
+                        Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                            if game.is_recording_gif() {
+                                match game.stop_gif() {
+                                    Ok(_) => osd.info("Recording saved"),
+                                    Err(e) => osd.error(&e),
+                                }
+                            } else {
+                                let path = game.output_path("gif");
+                                match game.start_gif(&path,
+                                                     gif_scale,
+                                                     &palettes[current_palette]) {
+                                    Ok(_) => osd.info(&format!("Recording to {}", path)),
+                                    Err(e) => osd.error(&e),
+                                }
+                            }
+                        }
+                        Event::KeyDown { keycode: Some(keycode), .. } => {
+                            if let Some(k) = game.keymap.get(keycode) {
+                                press(game, &mut netplay, Binding::Key(keycode), k);
+                            }
+                        }
+                        Event::KeyUp { keycode: Some(keycode), .. } => {
+                            release(game, &mut netplay, Binding::Key(keycode));
+                        }
+                        Event::ControllerButtonDown { which, button, .. } => {
+                            if let Some(k) = game.gamepad.get(button) {
+                                press(game, &mut netplay, Binding::Button(which, button), k);
+                            }
+                        }
+                        Event::ControllerButtonUp { which, button, .. } => {
+                            release(game, &mut netplay, Binding::Button(which, button));
+                        }
+                        Event::ControllerAxisMotion { which, axis, value, .. } => {
+                            let directions = gamepad::stick_directions(axis, value);
+                            for &(button, pushed) in directions.iter().flatten() {
+                                let binding = Binding::Stick(which, button);
+                                match game.gamepad.get(button) {
+                                    Some(k) if pushed => press(game, &mut netplay, binding, k),
+                                    _ => release(game, &mut netplay, binding),
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        if let Some(path) = play {
+            // A ROM that fails to load leaves the menu open
+            match launch(&path.to_string_lossy(),
+                         keymap_preset,
+                         record_gif.take(),
+                         record_audio.take(),
+                         gif_scale,
+                         &palettes[current_palette]) {
+                Ok(game) => {
+                    osd.info(&format!("Loaded {}", game.title));
+                    paused = false;
+                    screen = Screen::Game(Box::new(game));
+                    phosphor = phosphor_decay.map(Phosphor::new);
+                    last_frame = Instant::now();
+                }
+                Err(e) => osd.error(&e),
+            }
+            redraw = true;
         }
 
-        if last_frame.elapsed().subsec_nanos() > 100_000_000 / 60 {
-            cpu.emulate_cycle();
+        if back_to_menu {
+            if let Some(ref speaker) = speaker {
+                speaker.set_playing(false);
+            }
+            if netplay.take().is_some() {
+                osd.info("Netplay ended");
+            }
 
-            last_frame = Instant::now();
+            // Recordings are saved here, so that errors can still be shown.
+            // The list is read again to pick up ROMs added in the meantime.
+            let mut played = String::new();
+            if let Screen::Game(ref mut game) = screen {
+                for result in &[game.stop_gif(), game.stop_audio()] {
+                    match *result {
+                        Ok(true) => osd.info("Recording saved"),
+                        Ok(false) => {}
+                        Err(ref e) => osd.error(e),
+                    }
+                }
+                played = game.path.clone();
+            }
+            match Menu::scan(&rom_dir) {
+                Ok(mut menu) => {
+                    menu.select_path(Path::new(&played));
+                    screen = Screen::Menu(menu);
+                }
+                Err(e) => osd.error(&format!("Could not list ROMs: {}", e)),
+            }
+            redraw = true;
         }
 
-        // If the draw flag is set, update the screen
-        if cpu.draw_flag {
-            canvas.set_draw_color(Color::RGB(40, 40, 40));
-            canvas.clear();
+        if let Some(ref console) = console {
+            match screen {
+                Screen::Game(ref mut game) => console.poll(Some(game)),
+                Screen::Menu(_) => console.poll(None),
+            }
+        }
+
+        // The picture is updated every 1/60 s, running as many emulated frames as the speed asks.
+        // The afterglow, sound, recordings and overlay are updated along with it.
+        if last_frame.elapsed() >= FRAME_TIME {
+            let started = Instant::now();
+            if let Screen::Game(ref mut game) = screen {
+                let mut frames = if paused {
+                    advance_frames
+                } else if fast_forward {
+                    // Uncapped: as many frames as fit into the time until the next update
+                    usize::MAX
+                } else {
+                    pending_frames += SPEEDS[speed];
+                    let whole = pending_frames.floor();
+                    pending_frames -= whole;
+                    whole as usize
+                };
+                advance_frames = 0;
 
-            canvas.set_draw_color(Color::RGB(184, 186, 60));
-            for x in 0..64 {
-                for y in 0..32 {
-                    if cpu.gfx[(y * 64) + x] == 1 {
-                        canvas.fill_rect(Rect::new(x as i32 * 20, y as i32 * 20, 20, 20)).unwrap();
+                while frames > 0 && started.elapsed() < FRAME_TIME {
+                    // Waits for the other player's keys, the game goes on alone without them
+                    if let Some(ref mut session) = netplay {
+                        if let Err(e) = session.exchange(&mut game.cpu) {
+                            osd.error(&e);
+                            netplay = None;
+                        }
                     }
+                    if let Err(e) = game.frame(&palettes[current_palette]) {
+                        osd.error(&e);
+                    }
+                    osd.count_frames(1);
+
+                    if let Some(ref mut phosphor) = phosphor {
+                        phosphor.update(&game.cpu.gfx);
+                        if phosphor.is_fading() {
+                            redraw = true;
+                        }
+                    }
+                    frames -= 1;
+                }
+
+                if let Some(ref speaker) = speaker {
+                    speaker.set_playing(!paused && game.is_buzzing());
                 }
             }
-            cpu.draw_flag = false;
+
+            osd.paused = paused;
+            if osd.tick() {
+                redraw = true;
+            }
+
+            last_frame = started;
+        }
+
+        // If the screen changed, update the window
+        let drawn = match screen {
+            Screen::Menu(ref menu) if redraw => {
+                menu.draw(&mut canvas, &palettes[current_palette]);
+                true
+            }
+            Screen::Game(ref mut game) if game.cpu.gfx.is_dirty() || redraw => {
+                display::draw_screen(&mut canvas,
+                                     &mut screen_texture,
+                                     &game.cpu,
+                                     &palettes[current_palette],
+                                     phosphor.as_ref());
+                game.cpu.gfx.clear_dirty();
+                true
+            }
+            _ => false,
+        };
+
+        // The overlay is drawn over the finished picture, never into the emulated screen
+        if drawn {
+            osd.draw(&mut canvas, &palettes[current_palette]);
+            osd.count_redraw();
+            redraw = false;
         }
         canvas.present();
     }
 }
+
+// Load a ROM, starting the recordings given
+fn launch(path: &str,
+          keymap_preset: Option<&str>,
+          record_gif: Option<&str>,
+          record_audio: Option<&str>,
+          gif_scale: usize,
+          palette: &Palette)
+          -> Result<Game, String> {
+    let mut game = Game::load(path, keymap_preset)?;
+
+    if let Some(path) = record_gif {
+        game.start_gif(path, gif_scale, palette)?;
+        println!("Recording to {}", path);
+    }
+    if let Some(path) = record_audio {
+        game.start_audio(path)?;
+    }
+
+    Ok(game)
+}
+
+// A CHIP-8 key bound to several inputs goes down with the first of them
+// and up with the last one
+fn press(game: &mut Game, netplay: &mut Option<Netplay>, binding: Binding, key: usize) {
+    if game.held.press(binding, key) {
+        press_key(game, netplay, key, true);
+    }
+}
+
+fn release(game: &mut Game, netplay: &mut Option<Netplay>, binding: Binding) {
+    if let Some(key) = game.held.release(binding) {
+        press_key(game, netplay, key, false);
+    }
+}
+
+// Keypad presses go to the machine, or to the other player first during netplay
+fn press_key(game: &mut Game, netplay: &mut Option<Netplay>, key: usize, down: bool) {
+    match *netplay {
+        Some(ref mut netplay) if down => netplay.key_down(key),
+        Some(ref mut netplay) => netplay.key_up(key),
+        None if down => game.cpu.key_down(key),
+        None => game.cpu.key_up(key),
+    }
+}
+
+// ROMs loaded over the remote control get the same machine settings as in the window
+fn load_remote(path: &str) -> Result<(Chip8, usize), String> {
+    let mut game = Game::load(path, None)?;
+
+    Ok((mem::take(&mut game.cpu), game.cycles_per_frame))
+}
+
+fn toggle_fullscreen(window: &mut Window) {
+    let state = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+
+    window.set_fullscreen(state).unwrap();
+}
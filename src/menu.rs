@@ -0,0 +1,215 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use toml::Value;
+
+use config;
+use database;
+use display::color;
+use palette::{self, Palette};
+use romdb::RomInfo;
+use text::{self, ADVANCE, LINE_HEIGHT};
+
+// Files bigger than this can't be CHIP-8 programs, they are left out of the list
+const MAX_ROM_SIZE: u64 = 64 * 1024;
+
+// Number of entries PageUp/PageDown move by
+const PAGE: usize = 10;
+
+// The ROM directory defaults to roms/ and can be set in the global config:
+//
+// [launcher]
+// rom_dir = "/home/me/chip8"
+//
+// The --rom-dir option overrides both.
+pub fn rom_dir(option: Option<&str>) -> Result<PathBuf, String> {
+    if let Some(dir) = option {
+        return Ok(PathBuf::from(dir));
+    }
+
+    if let Some(path) = config::global_config() {
+        let section = config::read_section(&path, "launcher")?;
+        if let Some(dir) = section.as_ref().and_then(|s| s.get("rom_dir")).and_then(Value::as_str) {
+            return Ok(PathBuf::from(dir));
+        }
+    }
+
+    Ok(PathBuf::from("roms"))
+}
+
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: String,
+    pub info: Option<RomInfo>,
+}
+
+// List of the ROMs in a directory, drawn with the built-in font
+pub struct Menu {
+    pub dir: PathBuf,
+    pub entries: Vec<Entry>,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn scan(dir: &Path) -> Result<Menu, String> {
+        let listing = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+
+        let mut entries = Vec::new();
+        for item in listing.filter_map(|item| item.ok()) {
+            let path = item.path();
+            match item.metadata() {
+                Ok(ref metadata) if metadata.is_file() && metadata.len() <= MAX_ROM_SIZE => {}
+                _ => continue,
+            }
+
+            let mut rom = Vec::new();
+            if File::open(&path).and_then(|mut f| f.read_to_end(&mut rom)).is_err() {
+                continue;
+            }
+
+            // A broken user database shouldn't hide the list, the ROM just shows no details
+            let info = database::lookup(&rom).unwrap_or(None);
+            let name = item.file_name().to_string_lossy().into_owned();
+
+            entries.push(Entry { path, name, info });
+        }
+        entries.sort_by_key(|entry| entry.name.to_lowercase());
+
+        Ok(Menu {
+            dir: dir.to_path_buf(),
+            entries,
+            selected: 0,
+        })
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.get(self.selected)
+    }
+
+    // Select the ROM at `path` if it is in the list, so going back to the menu
+    // returns to the game that was just played
+    pub fn select_path(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|e| e.path == path) {
+            self.selected = index;
+        }
+    }
+
+    // Moving past either end of the list wraps around
+    pub fn select_previous(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + self.entries.len() - 1) % self.entries.len();
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + 1) % self.entries.len();
+        }
+    }
+
+    // Paging stops at the ends of the list
+    pub fn page_up(&mut self) {
+        self.selected = self.selected.saturating_sub(PAGE);
+    }
+
+    pub fn page_down(&mut self) {
+        self.selected = (self.selected + PAGE).min(self.entries.len().saturating_sub(1));
+    }
+
+    // The list takes the left part of the window, details of the selected ROM the right part
+    pub fn draw(&self, canvas: &mut Canvas<Window>, palette: &Palette) {
+        let (width, height) = canvas.output_size().unwrap();
+        let size = text::size_for((width, height));
+        let line = (LINE_HEIGHT * size) as i32;
+        let margin = line;
+
+        canvas.set_draw_color(color(palette.background()));
+        canvas.clear();
+
+        let foreground = color(palette.foreground());
+        let dimmed = color(palette::blend(palette.background(), palette.foreground(), 0.5));
+
+        canvas.set_draw_color(foreground);
+        text::draw(canvas, "CHIPPER", margin, margin, size);
+        canvas.set_draw_color(dimmed);
+        text::draw(canvas, &self.dir.to_string_lossy(), margin, margin + line, size);
+
+        let footer = height as i32 - margin - line;
+        text::draw(canvas, "ENTER: PLAY  BACKSPACE: MENU  ESC: QUIT", margin, footer, size);
+
+        let top = margin + 3 * line;
+        if self.entries.is_empty() {
+            canvas.set_draw_color(foreground);
+            text::draw(canvas, "NO ROMS FOUND", margin, top, size);
+            return;
+        }
+
+        // Keep the selection in view, scrolling the list a page at a time
+        let rows = ((footer - line - top) / line).max(1) as usize;
+        let first = self.selected / rows * rows;
+        let list_width = width / 3;
+        let columns = ((list_width / (ADVANCE * size)) as usize).max(1);
+
+        for (row, entry) in self.entries.iter().enumerate().skip(first).take(rows) {
+            let y = top + (row - first) as i32 * line;
+            let name = entry.info.as_ref().map_or(&entry.name, |info| &info.title);
+            let name: String = name.chars().take(columns.saturating_sub(2)).collect();
+
+            if row == self.selected {
+                canvas.set_draw_color(foreground);
+                text::draw(canvas, &format!("> {}", name), margin, y, size);
+            } else {
+                canvas.set_draw_color(dimmed);
+                text::draw(canvas, &format!("  {}", name), margin, y, size);
+            }
+        }
+
+        let left = margin + list_width as i32;
+        let columns = ((width as i32 - left - margin) / (ADVANCE * size) as i32).max(1) as usize;
+        let lines = self.selected().map_or_else(Vec::new, |entry| details(entry, columns));
+
+        canvas.set_draw_color(foreground);
+        for (row, text) in lines.iter().enumerate() {
+            let y = top + row as i32 * line;
+            if y + line > footer - line {
+                break;
+            }
+            text::draw(canvas, text, left, y, size);
+        }
+    }
+}
+
+// Lines of text describing a ROM, wrapped to `columns` characters
+fn details(entry: &Entry, columns: usize) -> Vec<String> {
+    let info = match entry.info {
+        Some(ref info) => info,
+        None => return text::wrap(&format!("{} - NOT IN THE ROM DATABASE", entry.name), columns),
+    };
+
+    let mut lines = text::wrap(&info.title, columns);
+
+    let mut credits = info.authors.join(", ");
+    if let Some(ref release) = info.release {
+        credits = format!("{} ({})", credits, release).trim().to_string();
+    }
+    lines.extend(text::wrap(&credits, columns));
+    if let Some(ref platform) = info.platform {
+        lines.extend(text::wrap(&format!("PLATFORM: {}", platform), columns));
+    }
+
+    if let Some(ref description) = info.description {
+        lines.push(String::new());
+        lines.extend(text::wrap(description, columns));
+    }
+
+    if !info.keys.is_empty() {
+        lines.push(String::new());
+        for (action, key) in &info.keys {
+            lines.extend(text::wrap(&format!("{:X}: {}", key, action), columns));
+        }
+    }
+
+    lines
+}
@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::time::Duration;
+use rand;
+
+use cpu::Chip8;
+
+// Two chipper instances playing one game over TCP. Both run the same machine and exchange
+// the keys held in every frame, so both machines get the same input. The keys of both
+// players are merged: two-player games like PONG2 put both players on one keypad.
+//
+// Frames advance in lockstep: a frame only runs once the keys of both players for it are known.
+// The keys held before frame n are sent for frame n + `delay`, and frame n runs with the keys
+// the other player sent `delay` frames earlier, so a frame only waits for the network when
+// the latency is longer than the delay. Nobody has pressed anything during the first `delay`
+// frames. Each side also sends the hash of its machine at frame n along with the keys, and
+// the session ends when the hashes differ (a desync).
+//
+// Messages:
+// hello - "C8NP", version, random seed (u32), delay (u8), hash of the fresh machine (u64)
+// frame - frame the keys are for (u32), keys held (u16, bit n for key n),
+//         hash of the machine `delay` frames before that (u64)
+// Numbers are little endian. The host sends its hello first and picks the seed and delay,
+// the other side answers with its own hello.
+
+const MAGIC: &[u8; 4] = b"C8NP";
+const VERSION: u8 = 2;
+const HELLO_SIZE: usize = 18;
+const FRAME_SIZE: usize = 14;
+
+// Frames the keys are sent ahead by default. More hides more network latency,
+// but the game takes longer to react to a key.
+pub const DEFAULT_DELAY: usize = 2;
+
+// How long to wait for the other player before giving up
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Netplay {
+    stream: TcpStream,
+    delay: usize,
+    // The frame about to be run
+    frame: u32,
+    // Keys held down on this side
+    keys: u16,
+    // Local keys of the coming frames, from `frame` on
+    local: VecDeque<u16>,
+    // Hashes of the local machine for the frames the other player's hashes haven't arrived for
+    hashes: VecDeque<u64>,
+}
+
+// Wait for the other player to connect to `listener`, then start the game on `cpu` afresh
+pub fn host(listener: &TcpListener, cpu: &mut Chip8, delay: usize) -> Result<Netplay, String> {
+    if delay > u8::MAX as usize {
+        return Err(format!("The delay can be at most {} frames", u8::MAX));
+    }
+    let (mut stream, address) = listener.accept().map_err(network_error)?;
+    println!("Netplay with {}", address);
+
+    let seed = rand::random::<u32>();
+    let hash = restart(cpu, seed);
+    stream.write_all(&hello(seed, delay as u8, hash)).map_err(network_error)?;
+    let (_, _, remote_hash) = read_hello(&mut stream)?;
+    if remote_hash != hash {
+        return Err("Netplay: the other player loaded a different ROM".to_string());
+    }
+
+    Netplay::new(stream, delay)
+}
+
+// Connect to a host at `address` and start the game on `cpu` the way the host does
+pub fn join(address: &str, cpu: &mut Chip8) -> Result<Netplay, String> {
+    let mut stream = TcpStream::connect(address).map_err(network_error)?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(network_error)?;
+
+    let (seed, delay, remote_hash) = read_hello(&mut stream)?;
+    let hash = restart(cpu, seed);
+    stream.write_all(&hello(seed, delay, hash)).map_err(network_error)?;
+    if remote_hash != hash {
+        return Err("Netplay: the other player loaded a different ROM".to_string());
+    }
+
+    Netplay::new(stream, delay as usize)
+}
+
+impl Netplay {
+    fn new(stream: TcpStream, delay: usize) -> Result<Netplay, String> {
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(network_error)?;
+        // Messages are tiny and sent once per frame, they shouldn't wait for more data
+        stream.set_nodelay(true).map_err(network_error)?;
+
+        Ok(Netplay {
+            stream,
+            delay,
+            frame: 0,
+            keys: 0,
+            local: VecDeque::from(vec![0; delay]),
+            hashes: VecDeque::with_capacity(delay + 1),
+        })
+    }
+
+    pub fn key_down(&mut self, key: usize) {
+        self.keys |= 1 << key;
+    }
+
+    pub fn key_up(&mut self, key: usize) {
+        self.keys &= !(1 << key);
+    }
+
+    // Frames run so far
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    // Call before every frame: sends the local keys for a later frame, waits for the other
+    // player's keys for this one and presses the keys of both players on `cpu`
+    pub fn exchange(&mut self, cpu: &mut Chip8) -> Result<(), String> {
+        let hash = state_hash(cpu);
+        let mut message = Vec::with_capacity(FRAME_SIZE);
+        message.extend_from_slice(&self.frame.wrapping_add(self.delay as u32).to_le_bytes());
+        message.extend_from_slice(&self.keys.to_le_bytes());
+        message.extend_from_slice(&hash.to_le_bytes());
+        self.stream.write_all(&message).map_err(network_error)?;
+        self.local.push_back(self.keys);
+        self.hashes.push_back(hash);
+
+        let remote_keys = if (self.frame as usize) < self.delay {
+            0
+        } else {
+            self.read_frame()?
+        };
+
+        let keys = self.local.pop_front().unwrap() | remote_keys;
+        for key in 0..16 {
+            if keys & (1 << key) != 0 {
+                cpu.key_down(key);
+            } else {
+                cpu.key_up(key);
+            }
+        }
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    // The other player's keys for this frame, sent `delay` frames ago
+    fn read_frame(&mut self) -> Result<u16, String> {
+        let mut message = [0; FRAME_SIZE];
+        self.stream.read_exact(&mut message).map_err(network_error)?;
+        let frame = u32::from_le_bytes([message[0], message[1], message[2], message[3]]);
+        let keys = u16::from_le_bytes([message[4], message[5]]);
+        let mut remote_hash = [0; 8];
+        remote_hash.copy_from_slice(&message[6..]);
+        if frame != self.frame {
+            return Err(format!("Netplay: expected frame {}, the other player sent {}",
+                               self.frame,
+                               frame));
+        }
+
+        // Both hashes are of the machine when it was about to run the frame `delay` frames ago
+        let hash = self.hashes.pop_front().unwrap();
+        if u64::from_le_bytes(remote_hash) != hash {
+            let frame = self.frame as usize - self.delay;
+            return Err(format!("Netplay: the games went out of sync at frame {}", frame));
+        }
+
+        Ok(keys)
+    }
+}
+
+// The other player can be a few frames behind and still has to read our messages,
+// so wait for it to close its side too. Closing with its messages unread would reset
+// the connection and lose ours.
+impl Drop for Netplay {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Write);
+        let _ = io::copy(&mut self.stream, &mut io::sink());
+    }
+}
+
+// Both sides start from a clean machine with the same random numbers
+fn restart(cpu: &mut Chip8, seed: u32) -> u64 {
+    cpu.seed(seed);
+    cpu.reset();
+    for key in 0..16 {
+        cpu.key_up(key);
+    }
+
+    state_hash(cpu)
+}
+
+fn hello(seed: u32, delay: u8, hash: u64) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HELLO_SIZE);
+    message.extend_from_slice(MAGIC);
+    message.push(VERSION);
+    message.extend_from_slice(&seed.to_le_bytes());
+    message.push(delay);
+    message.extend_from_slice(&hash.to_le_bytes());
+
+    message
+}
+
+fn read_hello(stream: &mut TcpStream) -> Result<(u32, u8, u64), String> {
+    let mut message = [0; HELLO_SIZE];
+    stream.read_exact(&mut message).map_err(network_error)?;
+    if &message[..4] != MAGIC {
+        return Err("Netplay: the other side isn't chipper".to_string());
+    }
+    if message[4] != VERSION {
+        return Err("Netplay: the other player runs a different version of chipper".to_string());
+    }
+
+    let seed = u32::from_le_bytes([message[5], message[6], message[7], message[8]]);
+    let mut hash = [0; 8];
+    hash.copy_from_slice(&message[10..]);
+
+    Ok((seed, message[9], u64::from_le_bytes(hash)))
+}
+
+// FNV-1a of the machine's save state
+pub fn state_hash(cpu: &Chip8) -> u64 {
+    cpu.save_state().iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+fn network_error(e: io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            "Netplay: the other player stopped responding".to_string()
+        }
+        io::ErrorKind::UnexpectedEof |
+        io::ErrorKind::BrokenPipe |
+        io::ErrorKind::ConnectionReset => "Netplay: the other player left".to_string(),
+        _ => format!("Netplay: {}", e),
+    }
+}
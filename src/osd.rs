@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use display::color;
+use palette::{Palette, Rgb};
+use text::{self, LINE_HEIGHT};
+
+// How long a message stays on screen
+const MESSAGE_TIME: u64 = 3;
+
+// Older messages are dropped when more than this pile up
+const MAX_MESSAGES: usize = 4;
+
+const ERROR_COLOR: Rgb = (255, 80, 80);
+
+struct Message {
+    text: String,
+    error: bool,
+    shown: Instant,
+}
+
+// On-screen display drawn over the window: messages, the pause state and (when enabled)
+// frame rate and emulation speed. It is drawn on the canvas after the emulated screen,
+// the CHIP-8 framebuffer is never touched.
+pub struct Osd {
+    pub show_stats: bool,
+    pub paused: bool,
+
+    messages: VecDeque<Message>,
+
+    // Counted over one second, then turned into the displayed numbers
+    since: Instant,
+    redraws: u32,
+    frames: u32,
+    fps: u32,
+    speed: u32,
+}
+
+impl Osd {
+    pub fn new() -> Osd {
+        Osd {
+            show_stats: false,
+            paused: false,
+            messages: VecDeque::new(),
+            since: Instant::now(),
+            redraws: 0,
+            frames: 0,
+            fps: 0,
+            speed: 0,
+        }
+    }
+
+    // Messages also go to stdout, so they end up in logs
+    pub fn info(&mut self, text: &str) {
+        println!("{}", text);
+        self.push(text, false);
+    }
+
+    pub fn error(&mut self, text: &str) {
+        println!("{}", text);
+        self.push(text, true);
+    }
+
+    fn push(&mut self, text: &str, error: bool) {
+        if self.messages.len() == MAX_MESSAGES {
+            self.messages.pop_front();
+        }
+
+        self.messages.push_back(Message {
+            text: text.to_string(),
+            error,
+            shown: Instant::now(),
+        });
+    }
+
+    // Count emulated frames, 60 per second is full speed
+    pub fn count_frames(&mut self, frames: u32) {
+        self.frames += frames;
+    }
+
+    pub fn count_redraw(&mut self) {
+        self.redraws += 1;
+    }
+
+    // Called every 1/60 s. Returns true when the overlay has to be redrawn,
+    // i.e. while it shows anything and once more after the last message is gone.
+    pub fn tick(&mut self) -> bool {
+        let count = self.messages.len();
+        self.messages.retain(|m| m.shown.elapsed() < Duration::from_secs(MESSAGE_TIME));
+        let expired = self.messages.len() != count;
+
+        if self.since.elapsed() >= Duration::from_secs(1) {
+            let elapsed = self.since.elapsed().as_secs_f32();
+            self.fps = (self.redraws as f32 / elapsed).round() as u32;
+            self.speed = (self.frames as f32 / elapsed / 60.0 * 100.0).round() as u32;
+            self.since = Instant::now();
+            self.redraws = 0;
+            self.frames = 0;
+        }
+
+        expired || self.show_stats || self.paused || !self.messages.is_empty()
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, palette: &Palette) {
+        let (width, height) = canvas.output_size().unwrap();
+        let size = text::size_for((width, height));
+        let line = (LINE_HEIGHT * size) as i32;
+        let margin = size as i32 * 2;
+
+        if self.show_stats {
+            let stats = format!("{} FPS  {}%", self.fps, self.speed);
+            label(canvas, palette, &stats, margin, margin, size, None);
+        }
+
+        if self.paused {
+            let x = width as i32 - margin - text::width("PAUSED", size) as i32;
+            label(canvas, palette, "PAUSED", x, margin, size, None);
+        }
+
+        // Newest message at the bottom
+        let mut y = height as i32 - margin - line;
+        for message in self.messages.iter().rev() {
+            let highlight = if message.error { Some(color(ERROR_COLOR)) } else { None };
+            label(canvas, palette, &message.text, margin, y, size, highlight);
+            y -= line + size as i32;
+        }
+    }
+}
+
+// Text on a box in the background colour, so it stays readable over the game
+fn label(canvas: &mut Canvas<Window>,
+         palette: &Palette,
+         text: &str,
+         x: i32,
+         y: i32,
+         size: u32,
+         highlight: Option<Color>) {
+    let padding = size as i32;
+    let background = Rect::new(x - padding,
+                               y - padding,
+                               text::width(text, size) + size,
+                               (LINE_HEIGHT - 2) * size + 2 * size);
+
+    canvas.set_draw_color(color(palette.background()));
+    canvas.fill_rect(background).unwrap();
+    canvas.set_draw_color(highlight.unwrap_or_else(|| color(palette.foreground())));
+    text::draw(canvas, text, x, y, size);
+}
@@ -0,0 +1,75 @@
+// Colours are plain RGB triples so the palette can be used outside of SDL (e.g. image export)
+pub type Rgb = (u8, u8, u8);
+
+// A palette holds one colour per combination of bitplanes:
+// 0 - background, 1 - first plane (the only one on plain CHIP-8), 2 - second plane, 3 - both planes.
+// Extended platforms such as XO-CHIP draw on two planes, classic games only ever use 0 and 1.
+#[derive(Clone)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [Rgb; 4],
+}
+
+// name, background, plane 1, plane 2, both planes
+const THEMES: [(&str, Rgb, Rgb, Rgb, Rgb); 6] = [
+    ("classic", (40, 40, 40), (184, 186, 60), (200, 90, 60), (240, 230, 200)),
+    ("mono", (0, 0, 0), (255, 255, 255), (128, 128, 128), (192, 192, 192)),
+    // Maximum luminance difference between every pair of colours
+    ("high-contrast", (0, 0, 0), (255, 255, 0), (0, 255, 255), (255, 255, 255)),
+    // Okabe-Ito colours, distinguishable with all common forms of colour blindness
+    ("colorblind", (0, 0, 0), (230, 159, 0), (86, 180, 233), (240, 228, 66)),
+    ("amber", (20, 12, 0), (255, 176, 0), (160, 100, 0), (255, 220, 140)),
+    ("green", (5, 20, 5), (51, 255, 51), (20, 140, 20), (180, 255, 180)),
+];
+
+impl Palette {
+    pub fn theme(name: &str) -> Result<Palette, String> {
+        for &(theme, bg, fg, plane2, both) in THEMES.iter() {
+            if theme == name.to_lowercase() {
+                return Ok(Palette {
+                    name: theme.to_string(),
+                    colors: [bg, fg, plane2, both],
+                });
+            }
+        }
+
+        Err(format!("Unknown theme \"{}\" (available: {})", name, theme_names().join(", ")))
+    }
+
+    pub fn background(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Rgb {
+        self.colors[1]
+    }
+}
+
+pub fn theme_names() -> Vec<&'static str> {
+    THEMES.iter().map(|t| t.0).collect()
+}
+
+// Every built-in theme, in the order the palette hotkey cycles through them
+pub fn themes() -> Vec<Palette> {
+    theme_names().iter().map(|name| Palette::theme(name).unwrap()).collect()
+}
+
+// Mix two colours, an `amount` of 0.0 gives `from` and 1.0 gives `to`
+pub fn blend(from: Rgb, to: Rgb, amount: f32) -> Rgb {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+// Parse colours written as RRGGBB or #RRGGBB
+pub fn parse_color(color: &str) -> Result<Rgb, String> {
+    let hex = color.trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(format!("\"{}\" is not a RRGGBB colour", color));
+    }
+
+    match u32::from_str_radix(hex, 16) {
+        Ok(rgb) => Ok(((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)),
+        Err(_) => Err(format!("\"{}\" is not a RRGGBB colour", color)),
+    }
+}
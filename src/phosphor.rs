@@ -0,0 +1,51 @@
+use framebuffer::Framebuffer;
+
+// Brightness below which a fading pixel counts as fully dark
+const CUTOFF: f32 = 0.02;
+
+// Emulates the afterglow of a CRT screen.
+// CHIP-8 games erase and redraw sprites with XOR, so on a modern display moving sprites flicker.
+// Instead of switching pixels off instantly, each pixel keeps a brightness level that is reset
+// to full while the pixel is lit and multiplied by `decay` on every 60 Hz frame after that.
+pub struct Phosphor {
+    decay: f32,
+    levels: Vec<f32>,
+}
+
+impl Phosphor {
+    // `decay` is the part of brightness kept per frame, 0.0 disables the afterglow entirely
+    pub fn new(decay: f32) -> Phosphor {
+        Phosphor {
+            decay: decay.clamp(0.0, 1.0),
+            levels: Vec::new(),
+        }
+    }
+
+    // Advance the afterglow by one frame using the current contents of the framebuffer
+    pub fn update(&mut self, gfx: &Framebuffer) {
+        let pixels = gfx.pixels().count();
+        if self.levels.len() != pixels {
+            self.levels = vec![0.0; pixels];
+        }
+
+        for (level, pixel) in self.levels.iter_mut().zip(gfx.pixels()) {
+            if pixel {
+                *level = 1.0;
+            } else if *level > CUTOFF {
+                *level *= self.decay;
+            } else {
+                *level = 0.0;
+            }
+        }
+    }
+
+    // Brightness of each pixel, 0.0 (off) to 1.0 (lit), in framebuffer order
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+
+    // True while some pixel is still fading out and the screen has to be redrawn
+    pub fn is_fading(&self) -> bool {
+        self.levels.iter().any(|&level| level > 0.0 && level < 1.0)
+    }
+}
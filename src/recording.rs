@@ -0,0 +1,128 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use gif::{Encoder, Frame, Repeat};
+
+use image;
+use palette::Palette;
+
+// Frames are captured at the 60 Hz display rate, GIF delays are counted in 1/100 s
+const FRAMES_PER_SECOND: u64 = 60;
+
+// Most viewers show frames with a shorter delay for 1/10 s instead
+pub const MIN_DELAY: u16 = 2;
+
+// Records the screen into an animated GIF.
+// Every CHIP-8 pixel value is used directly as an index into the palette, so the GIF is lossless.
+// Identical consecutive frames are merged into one frame that is shown for longer, and a frame
+// that would be on screen for less than MIN_DELAY is dropped in favour of the next one.
+pub struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    palette: Vec<u8>,
+    resolution: (usize, usize),
+    scale: usize,
+
+    // The last captured frame is only written once a different one comes in (or recording stops),
+    // because only then it's known how long it stays on screen
+    pending: Option<(Vec<u8>, Vec<u8>)>,
+    pending_frames: u64,
+    written_frames: u64,
+}
+
+impl GifRecorder {
+    pub fn create(path: &str,
+                  resolution: (usize, usize),
+                  scale: usize,
+                  palette: &Palette)
+                  -> io::Result<GifRecorder> {
+        let file = BufWriter::new(File::create(path)?);
+        let colors = flatten(palette);
+
+        let (width, height) = (resolution.0 * scale, resolution.1 * scale);
+        let mut encoder = Encoder::new(file, width as u16, height as u16, &colors)
+            .map_err(gif_error)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?;
+
+        Ok(GifRecorder {
+            encoder,
+            palette: colors,
+            resolution,
+            scale,
+            pending: None,
+            pending_frames: 0,
+            written_frames: 0,
+        })
+    }
+
+    // Add one 60 Hz frame to the recording
+    pub fn capture(&mut self, gfx: &[u8], palette: &Palette) -> io::Result<()> {
+        let colors = flatten(palette);
+
+        let repeated = match self.pending {
+            Some((ref pixels, ref pending_colors)) => pixels[..] == *gfx && *pending_colors == colors,
+            None => false,
+        };
+
+        if repeated {
+            self.pending_frames += 1;
+        } else if self.pending.is_some() && self.pending_delay() < MIN_DELAY as u64 {
+            // The next frame takes over the time of the one it replaces
+            self.pending = Some((gfx.to_vec(), colors));
+            self.pending_frames += 1;
+        } else {
+            self.flush()?;
+            self.pending = Some((gfx.to_vec(), colors));
+            self.pending_frames = 1;
+        }
+
+        Ok(())
+    }
+
+    // Write the last frame and close the file
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()?;
+        self.encoder.into_inner()?;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let (pixels, colors) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        // Only the last frame can still be too short
+        let delay = self.pending_delay().clamp(MIN_DELAY as u64, u16::MAX as u64) as u16;
+        self.written_frames += self.pending_frames;
+
+        let (width, height) = (self.resolution.0 * self.scale, self.resolution.1 * self.scale);
+        let frame = Frame {
+            width: width as u16,
+            height: height as u16,
+            delay,
+            buffer: image::scale(&pixels, self.resolution, self.scale).into(),
+            // Only frames drawn after the theme was switched need a palette of their own
+            palette: if colors != self.palette { Some(colors) } else { None },
+            ..Frame::default()
+        };
+
+        self.encoder.write_frame(&frame).map_err(gif_error)
+    }
+
+    // Delays are computed from the total time, so rounding errors don't add up
+    fn pending_delay(&self) -> u64 {
+        centiseconds(self.written_frames + self.pending_frames) - centiseconds(self.written_frames)
+    }
+}
+
+fn centiseconds(frames: u64) -> u64 {
+    (frames * 100 + FRAMES_PER_SECOND / 2) / FRAMES_PER_SECOND
+}
+
+fn flatten(palette: &Palette) -> Vec<u8> {
+    palette.colors.iter().flat_map(|&(r, g, b)| vec![r, g, b]).collect()
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+    io::Error::other(e)
+}
@@ -0,0 +1,402 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use serde_json::{self, Map, Value};
+
+use cpu::{Chip8, Fault, CYCLES_PER_FRAME, MEMORY_SIZE};
+
+// Remote control of a Chip8 with JSON-RPC 2.0, so scripts and bots written in any language can
+// drive the emulator. Every request and every response is one line of JSON:
+//
+// --> {"jsonrpc": "2.0", "id": 1, "method": "run", "params": {"frames": 60}}
+// <-- {"id":1,"jsonrpc":"2.0","result":null}
+//
+// Methods:
+// load {path}                         load a ROM and start it
+// reset                               restart the ROM
+// step {count = 1}                    execute up to 1000000 instructions
+// run {frames = 1}                    emulate up to 3600 frames at 60 Hz
+// key_down {key}, key_up {key}        press and release keypad keys 0-15
+// peek {address, length = 1}          read memory, returns a list of bytes
+// poke {address, bytes}               write a list of bytes to memory
+// registers                           returns v0-vf, i, pc, delay, sound and stack
+// set_registers {v0-vf, i, pc, delay, sound}   change any of these registers
+// screen                              returns width, height and the rows of pixels, each as
+//                                     a hex number with the leftmost pixel in the highest bit
+// save_state                          returns {state} as a hex string
+// load_state {state}
+
+// Error codes defined by JSON-RPC
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// The request was fine but couldn't be carried out, e.g. the program stopped with a fault
+const FAILED: i64 = -32000;
+
+// A single request can't keep the server busy for long, other clients are waiting
+const MAX_STEPS: u64 = 1_000_000;
+const MAX_FRAMES: u64 = 60 * 60;
+
+// Loads a ROM into a fresh Chip8 and picks its speed in instructions per frame.
+// Frontends pass their own, e.g. to apply the ROM database.
+pub type Loader = fn(&str) -> Result<(Chip8, usize), String>;
+
+// Where the server reports what happens to it, e.g. clients that disconnected with an error
+pub type Logger = fn(&str);
+
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i64, message: &str) -> Error {
+        Error {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+// The machine being controlled. It stays the same across connections.
+pub struct Session {
+    pub cpu: Chip8,
+    pub cycles_per_frame: usize,
+    // Set when the program stopped with a fault, until it is reset or another state is loaded
+    fault: Option<Fault>,
+    loader: Loader,
+}
+
+// ROMs loaded with the default machine settings
+pub fn load_rom(path: &str) -> Result<(Chip8, usize), String> {
+    let mut cpu = Chip8::new();
+    cpu.load_game(path).map_err(|e| format!("Could not load {}: {}", path, e))?;
+
+    Ok((cpu, CYCLES_PER_FRAME))
+}
+
+impl Session {
+    pub fn new(cpu: Chip8, cycles_per_frame: usize, loader: Loader) -> Session {
+        Session {
+            cpu,
+            cycles_per_frame,
+            fault: None,
+            loader,
+        }
+    }
+
+    // Answer one request. Notifications (requests without an id) get no answer.
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        let (id, result) = match serde_json::from_str::<Value>(request) {
+            Ok(Value::Object(request)) => {
+                let id = request.get("id").cloned();
+                let result = self.dispatch(&request);
+                match id {
+                    Some(id) => (id, result),
+                    None => return None,
+                }
+            }
+            Ok(_) => (Value::Null, Err(Error::new(INVALID_REQUEST, "request is not an object"))),
+            Err(e) => (Value::Null, Err(Error::new(PARSE_ERROR, &e.to_string()))),
+        };
+
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => {
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {"code": e.code, "message": e.message},
+                })
+            }
+        };
+        Some(response.to_string())
+    }
+
+    // Answer the requests of one client until it disconnects
+    pub fn serve_client<S: Read + Write>(&mut self, stream: S) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 0 {
+            if !line.trim().is_empty() {
+                if let Some(response) = self.handle(&line) {
+                    let stream = reader.get_mut();
+                    stream.write_all(response.as_bytes())?;
+                    stream.write_all(b"\n")?;
+                    stream.flush()?;
+                }
+            }
+            line.clear();
+        }
+
+        Ok(())
+    }
+
+    fn dispatch(&mut self, request: &Map<String, Value>) -> Result<Value, Error> {
+        let method = match request.get("method") {
+            Some(Value::String(method)) => method.as_str(),
+            _ => return Err(Error::new(INVALID_REQUEST, "method is missing")),
+        };
+        let no_params = Map::new();
+        let params = match request.get("params") {
+            None | Some(Value::Null) => &no_params,
+            Some(Value::Object(params)) => params,
+            Some(_) => return Err(Error::new(INVALID_PARAMS, "params have to be an object")),
+        };
+
+        match method {
+            "load" => {
+                let (cpu, cycles_per_frame) = (self.loader)(string(params, "path")?)
+                    .map_err(|e| Error::new(FAILED, &e))?;
+                self.cpu = cpu;
+                self.cycles_per_frame = cycles_per_frame;
+                self.fault = None;
+                Ok(Value::Null)
+            }
+            "reset" => {
+                self.cpu.reset();
+                self.fault = None;
+                Ok(Value::Null)
+            }
+            "step" => {
+                for _ in 0..number(params, "count", Some(1), MAX_STEPS)? {
+                    self.check_fault()?;
+                    let result = self.cpu.emulate_cycle();
+                    self.stop(result)?;
+                }
+                Ok(Value::Null)
+            }
+            "run" => {
+                for _ in 0..number(params, "frames", Some(1), MAX_FRAMES)? {
+                    self.check_fault()?;
+                    let result = self.cpu.run_frame(self.cycles_per_frame);
+                    self.stop(result)?;
+                }
+                Ok(Value::Null)
+            }
+            "key_down" => {
+                self.cpu.key_down(number(params, "key", None, 0xF)? as usize);
+                Ok(Value::Null)
+            }
+            "key_up" => {
+                self.cpu.key_up(number(params, "key", None, 0xF)? as usize);
+                Ok(Value::Null)
+            }
+            "peek" => {
+                let address = number(params, "address", None, MEMORY_SIZE as u64 - 1)? as usize;
+                let length = number(params, "length", Some(1), (MEMORY_SIZE - address) as u64)?;
+                let bytes = &self.cpu.memory()[address..address + length as usize];
+                Ok(json!(bytes))
+            }
+            "poke" => {
+                let address = number(params, "address", None, MEMORY_SIZE as u64 - 1)? as usize;
+                let bytes = bytes(params)?;
+                if address + bytes.len() > MEMORY_SIZE {
+                    return Err(Error::new(INVALID_PARAMS, "bytes go past the end of memory"));
+                }
+                for (offset, &byte) in bytes.iter().enumerate() {
+                    self.cpu.poke(address + offset, byte);
+                }
+                Ok(Value::Null)
+            }
+            "registers" => Ok(self.registers()),
+            "set_registers" => self.set_registers(params),
+            "screen" => {
+                let rows: Vec<String> =
+                    self.cpu.gfx.rows().map(|row| format!("{:016x}", row)).collect();
+                let (width, height) = self.cpu.resolution();
+                Ok(json!({"width": width, "height": height, "rows": rows}))
+            }
+            "save_state" => Ok(json!({"state": to_hex(&self.cpu.save_state())})),
+            "load_state" => {
+                let state = from_hex(string(params, "state")?)?;
+                self.cpu.load_state(&state).map_err(|e| Error::new(FAILED, &e))?;
+                self.fault = None;
+                Ok(Value::Null)
+            }
+            _ => Err(Error::new(METHOD_NOT_FOUND, &format!("unknown method {}", method))),
+        }
+    }
+
+    fn check_fault(&self) -> Result<(), Error> {
+        match self.fault {
+            Some(fault) => Err(Error::new(FAILED, &format!("program stopped: {}", fault))),
+            None => Ok(()),
+        }
+    }
+
+    fn stop(&mut self, result: Result<(), Fault>) -> Result<(), Error> {
+        result.map_err(|fault| {
+            self.fault = Some(fault);
+            Error::new(FAILED, &format!("stopped: {} at {:03X}", fault, self.cpu.pc()))
+        })
+    }
+
+    fn registers(&self) -> Value {
+        let mut registers = Map::new();
+        for (x, &value) in self.cpu.v().iter().enumerate() {
+            registers.insert(format!("v{:x}", x), json!(value));
+        }
+        registers.insert("i".to_string(), json!(self.cpu.i()));
+        registers.insert("pc".to_string(), json!(self.cpu.pc()));
+        registers.insert("delay".to_string(), json!(self.cpu.delay_timer()));
+        registers.insert("sound".to_string(), json!(self.cpu.sound_timer()));
+        registers.insert("stack".to_string(), json!(self.cpu.stack()));
+
+        Value::Object(registers)
+    }
+
+    // All names are checked before anything changes
+    fn set_registers(&mut self, params: &Map<String, Value>) -> Result<Value, Error> {
+        let mut changes = Vec::new();
+        for name in params.keys() {
+            let max = match name.as_str() {
+                "i" | "pc" => 0xFFFF,
+                _ => 0xFF,
+            };
+            let value = number(params, name, None, max)? as u16;
+            let register = match name.as_str() {
+                "i" | "pc" | "delay" | "sound" => None,
+                _ => {
+                    let x = name.strip_prefix('v').and_then(|x| u8::from_str_radix(x, 16).ok());
+                    match x {
+                        Some(x) if x < 16 && name.len() == 2 => Some(x as usize),
+                        _ => {
+                            let message = format!("unknown register {}", name);
+                            return Err(Error::new(INVALID_PARAMS, &message));
+                        }
+                    }
+                }
+            };
+            changes.push((name.as_str(), register, value));
+        }
+
+        for (name, register, value) in changes {
+            match (name, register) {
+                (_, Some(x)) => self.cpu.set_v(x, value as u8),
+                ("i", _) => self.cpu.set_i(value),
+                ("pc", _) => self.cpu.set_pc(value),
+                ("delay", _) => self.cpu.set_delay_timer(value as u8),
+                _ => self.cpu.set_sound_timer(value as u8),
+            }
+        }
+
+        Ok(Value::Null)
+    }
+}
+
+// Serve clients one after the other, until the listener fails.
+// `address` is the path of a Unix socket if it contains a '/', otherwise a host:port
+// that has to be on the loopback interface: the API gives full control over the machine.
+pub fn serve(address: &str, session: &mut Session, log: Logger) -> io::Result<()> {
+    if address.contains('/') {
+        return serve_unix(address, session, log);
+    }
+
+    let addresses: Vec<_> = address.to_socket_addrs()?.collect();
+    if addresses.iter().any(|address| !address.ip().is_loopback()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "only loopback addresses are allowed"));
+    }
+
+    let listener = TcpListener::bind(&addresses[..])?;
+    log(&format!("Remote control listening on {}", listener.local_addr()?));
+    for stream in listener.incoming() {
+        if let Err(e) = session.serve_client(stream?) {
+            log(&format!("Remote client disconnected: {}", e));
+        }
+    }
+
+    Ok(())
+}
+
+// The socket file is removed when the server stops. One left behind by a server that was
+// killed is removed before binding, anything else at `path` is left alone.
+#[cfg(unix)]
+fn serve_unix(path: &str, session: &mut Session, log: Logger) -> io::Result<()> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    log(&format!("Remote control listening on {}", path));
+
+    let mut result = Ok(());
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = session.serve_client(stream) {
+                    log(&format!("Remote client disconnected: {}", e));
+                }
+            }
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    let _ = fs::remove_file(path);
+
+    result
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_: &str, _: &mut Session, _: Logger) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::InvalidInput, "Unix sockets are not supported here"))
+}
+
+fn string<'a>(params: &'a Map<String, Value>, name: &str) -> Result<&'a str, Error> {
+    params.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::new(INVALID_PARAMS, &format!("{} has to be a string", name)))
+}
+
+// A whole number from 0 to `max`, `default` if it's left out
+fn number(params: &Map<String, Value>,
+          name: &str,
+          default: Option<u64>,
+          max: u64)
+          -> Result<u64, Error> {
+    let value = match params.get(name) {
+        None => default,
+        Some(value) => value.as_u64(),
+    };
+    match value {
+        Some(value) if value <= max => Ok(value),
+        _ => {
+            let message = format!("{} has to be a number from 0 to {}", name, max);
+            Err(Error::new(INVALID_PARAMS, &message))
+        }
+    }
+}
+
+fn bytes(params: &Map<String, Value>) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::new(INVALID_PARAMS, "bytes have to be a list of numbers from 0 to 255");
+    let list = params.get("bytes").and_then(Value::as_array).ok_or_else(invalid)?;
+
+    list.iter()
+        .map(|byte| byte.as_u64().filter(|&byte| byte <= 0xFF).map(|byte| byte as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::new(INVALID_PARAMS, "state has to be a hex string");
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use serde_json;
+use sha1::{Digest, Sha1};
+
+use cpu::{Quirks, MAX_STACK_DEPTH, VIP_STACK_DEPTH};
+
+// ROMs are identified by the SHA-1 of their contents and looked up in a database that uses
+// the format of the community chip-8-database (https://github.com/chip-8/chip-8-database):
+//
+// sha1-hashes.json - maps the SHA-1 of a ROM to an index in programs.json
+// programs.json    - list of programs, each with its known ROM files
+//
+// The bundled database describes the ROMs in roms/. Frontends can read a full copy of the
+// community database and search it first.
+const BUNDLED_HASHES: &str = include_str!("../data/sha1-hashes.json");
+const BUNDLED_PROGRAMS: &str = include_str!("../data/programs.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    description: Option<String>,
+    release: Option<String>,
+    #[serde(default)]
+    authors: Vec<String>,
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: HashMap<String, QuirkOverrides>,
+    tickrate: Option<usize>,
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+}
+
+// Quirks the database lists for a ROM, on top of the defaults of its platform.
+// The database knows more quirks than chipper implements, those are ignored.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct QuirkOverrides {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    jump: Option<bool>,
+    logic: Option<bool>,
+    wrap: Option<bool>,
+}
+
+// Everything known about a ROM
+pub struct RomInfo {
+    pub sha1: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    pub platform: Option<String>,
+    pub quirks: Quirks,
+    pub stack_depth: Option<usize>,
+    // Instructions per frame
+    pub tickrate: Option<usize>,
+    // What the game uses keys for, e.g. "left" -> 0x4
+    pub keys: BTreeMap<String, u8>,
+}
+
+pub struct Database {
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+}
+
+impl Database {
+    // Keys outside the keypad are rejected here, before they can be pressed
+    pub fn parse(hashes: &str, programs: &str) -> Result<Database, String> {
+        let database = Database {
+            hashes: serde_json::from_str(hashes).map_err(|e| format!("sha1-hashes.json: {}", e))?,
+            programs: serde_json::from_str(programs).map_err(|e| format!("programs.json: {}", e))?,
+        };
+
+        for program in &database.programs {
+            for rom in program.roms.values() {
+                if let Some((action, key)) = rom.keys.iter().find(|&(_, &key)| key > 0xF) {
+                    return Err(format!("programs.json: {} binds \"{}\" to {}, which is not a \
+                                        CHIP-8 key (0-F)",
+                                       program.title,
+                                       action,
+                                       key));
+                }
+            }
+        }
+
+        Ok(database)
+    }
+
+    pub fn bundled() -> Database {
+        Database::parse(BUNDLED_HASHES, BUNDLED_PROGRAMS).unwrap()
+    }
+
+    // A copy of the database in `dir`, if there is one
+    pub fn read(dir: &Path) -> Result<Option<Database>, String> {
+        match (read(&dir.join("sha1-hashes.json")), read(&dir.join("programs.json"))) {
+            (Some(hashes), Some(programs)) => Database::parse(&hashes, &programs).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn find(&self, sha1: &str) -> Option<RomInfo> {
+        let program = self.hashes.get(sha1).and_then(|&index| self.programs.get(index))?;
+        let rom = program.roms.get(sha1)?;
+
+        let platform = rom.platforms.first().cloned();
+        let mut quirks = platform.as_ref().map_or_else(Quirks::default, |p| platform_quirks(p));
+        if let Some(overrides) = platform.as_ref().and_then(|p| rom.quirky_platforms.get(p)) {
+            overrides.apply(&mut quirks);
+        }
+        let stack_depth = platform.as_ref().and_then(|p| platform_stack_depth(p));
+
+        Some(RomInfo {
+            sha1: sha1.to_string(),
+            title: program.title.clone(),
+            description: program.description.clone(),
+            authors: program.authors.clone(),
+            release: program.release.clone(),
+            platform,
+            quirks,
+            stack_depth,
+            tickrate: rom.tickrate,
+            keys: rom.keys.clone(),
+        })
+    }
+}
+
+impl QuirkOverrides {
+    fn apply(&self, quirks: &mut Quirks) {
+        let settings = [(self.shift, &mut quirks.shift),
+                        (self.memory_increment_by_x, &mut quirks.memory_increment_by_x),
+                        (self.memory_leave_i_unchanged, &mut quirks.memory_leave_i_unchanged),
+                        (self.jump, &mut quirks.jump),
+                        (self.logic, &mut quirks.logic),
+                        (self.wrap, &mut quirks.wrap)];
+
+        for (value, quirk) in settings {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+    }
+}
+
+// Quirks of the platforms in the community database
+fn platform_quirks(platform: &str) -> Quirks {
+    let (shift, memory_increment_by_x, memory_leave_i_unchanged, jump, logic, wrap) =
+        match platform {
+            "originalChip8" | "hybridVIP" => (false, false, false, false, true, false),
+            "modernChip8" => (false, false, false, false, false, false),
+            "xochip" => (false, false, false, false, false, true),
+            "chip48" => (true, true, false, true, false, false),
+            "superchip1" | "superchip" => (true, false, true, true, false, false),
+            _ => return Quirks::default(),
+        };
+
+    Quirks {
+        shift,
+        memory_increment_by_x,
+        memory_leave_i_unchanged,
+        jump,
+        logic,
+        wrap,
+    }
+}
+
+// Stack levels of the platforms in the community database
+fn platform_stack_depth(platform: &str) -> Option<usize> {
+    match platform {
+        "originalChip8" | "hybridVIP" => Some(VIP_STACK_DEPTH),
+        "modernChip8" | "xochip" | "chip48" | "superchip1" | "superchip" => Some(MAX_STACK_DEPTH),
+        _ => None,
+    }
+}
+
+pub fn sha1(rom: &[u8]) -> String {
+    Sha1::digest(rom).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn read(path: &Path) -> Option<String> {
+    let mut contents = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut contents)).ok()?;
+
+    Some(contents)
+}
@@ -0,0 +1,31 @@
+use rodio::{get_default_endpoint, Sink};
+use rodio::source::SineWave;
+
+use buzzer::TONE;
+
+// Plays the buzzer through the default audio device.
+// A never ending tone is queued once and paused or resumed as the sound timer changes.
+pub struct Speaker {
+    sink: Sink,
+}
+
+impl Speaker {
+    // Returns None when there is no audio device
+    pub fn new() -> Option<Speaker> {
+        get_default_endpoint().map(|endpoint| {
+            let sink = Sink::new(&endpoint);
+            sink.pause();
+            sink.append(SineWave::new(TONE));
+
+            Speaker { sink }
+        })
+    }
+
+    pub fn set_playing(&self, on: bool) {
+        if on && self.sink.is_paused() {
+            self.sink.play();
+        } else if !on && !self.sink.is_paused() {
+            self.sink.pause();
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+// Built-in 3x5 pixel font for the frontend's own text (menus and messages).
+// Lowercase letters are drawn as uppercase, unknown characters as '?'.
+const GLYPHS: [(char, [&str; 5]); 60] = [
+    ('A', [".#.", "#.#", "###", "#.#", "#.#"]),
+    ('B', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('C', [".##", "#..", "#..", "#..", ".##"]),
+    ('D', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('E', ["###", "#..", "##.", "#..", "###"]),
+    ('F', ["###", "#..", "##.", "#..", "#.."]),
+    ('G', [".##", "#..", "#.#", "#.#", ".##"]),
+    ('H', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..#", "..#", "..#", "#.#", ".#."]),
+    ('K', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('L', ["#..", "#..", "#..", "#..", "###"]),
+    ('M', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('N', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('O', [".#.", "#.#", "#.#", "#.#", ".#."]),
+    ('P', ["##.", "#.#", "##.", "#..", "#.."]),
+    ('Q', [".#.", "#.#", "#.#", "##.", ".##"]),
+    ('R', ["##.", "#.#", "##.", "#.#", "#.#"]),
+    ('S', [".##", "#..", ".#.", "..#", "##."]),
+    ('T', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('U', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('V', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('W', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('X', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('Y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('Z', ["###", "..#", ".#.", "#..", "###"]),
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["##.", "..#", ".#.", "#..", "###"]),
+    ('3', ["##.", "..#", ".#.", "..#", "##."]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "##.", "..#", "##."]),
+    ('6', [".##", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", ".#.", ".#.", ".#."]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "##."]),
+    (' ', ["...", "...", "...", "...", "..."]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    (',', ["...", "...", "...", ".#.", "#.."]),
+    (':', ["...", ".#.", "...", ".#.", "..."]),
+    (';', ["...", ".#.", "...", ".#.", "#.."]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('_', ["...", "...", "...", "...", "###"]),
+    ('/', ["..#", "..#", ".#.", "#..", "#.."]),
+    ('(', [".#.", "#..", "#..", "#..", ".#."]),
+    (')', [".#.", "..#", "..#", "..#", ".#."]),
+    ('[', ["##.", "#..", "#..", "#..", "##."]),
+    (']', [".##", "..#", "..#", "..#", ".##"]),
+    ('<', ["..#", ".#.", "#..", ".#.", "..#"]),
+    ('>', ["#..", ".#.", "..#", ".#.", "#.."]),
+    ('!', [".#.", ".#.", ".#.", "...", ".#."]),
+    ('?', ["##.", "..#", ".#.", "...", ".#."]),
+    ('\'', [".#.", ".#.", "...", "...", "..."]),
+    ('"', ["#.#", "#.#", "...", "...", "..."]),
+    ('+', ["...", ".#.", "###", ".#.", "..."]),
+    ('=', ["...", "###", "...", "###", "..."]),
+    ('*', ["...", "#.#", ".#.", "#.#", "..."]),
+    ('%', ["#.#", "..#", ".#.", "#..", "#.#"]),
+    ('#', ["#.#", "###", "#.#", "###", "#.#"]),
+    ('&', [".#.", "#.#", ".#.", "#.#", ".##"]),
+];
+
+// Size of a character cell in font pixels, including the spacing to the next one
+pub const ADVANCE: u32 = 4;
+pub const LINE_HEIGHT: u32 = 7;
+
+fn glyph(c: char) -> &'static [&'static str; 5] {
+    let c = c.to_ascii_uppercase();
+
+    GLYPHS.iter()
+        .find(|&&(g, _)| g == c)
+        .or_else(|| GLYPHS.iter().find(|&&(g, _)| g == '?'))
+        .map(|(_, rows)| rows)
+        .unwrap()
+}
+
+// Draw `text` with its top left corner at (x, y) in the current draw colour.
+// Every font pixel becomes a `size` x `size` square.
+pub fn draw(canvas: &mut Canvas<Window>, text: &str, x: i32, y: i32, size: u32) {
+    let mut rects = Vec::new();
+
+    for (column, c) in text.chars().enumerate() {
+        let left = x + (column as u32 * ADVANCE * size) as i32;
+        for (row, pixels) in glyph(c).iter().enumerate() {
+            for (offset, pixel) in pixels.chars().enumerate() {
+                if pixel == '#' {
+                    rects.push(Rect::new(left + (offset as u32 * size) as i32,
+                                         y + (row as u32 * size) as i32,
+                                         size,
+                                         size));
+                }
+            }
+        }
+    }
+
+    if !rects.is_empty() {
+        canvas.fill_rects(&rects).unwrap();
+    }
+}
+
+// Width of `text` in window pixels
+pub fn width(text: &str, size: u32) -> u32 {
+    text.chars().count() as u32 * ADVANCE * size
+}
+
+// Font pixel size for a window of `output` pixels, so text stays readable when the window grows
+pub fn size_for(output: (u32, u32)) -> u32 {
+    (output.0 / 256).min(output.1 / 128).max(1)
+}
+
+// Break `text` into lines of at most `columns` characters, at spaces where possible
+pub fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word = word.to_string();
+        while word.chars().count() > columns {
+            let rest = word.split_off(word.char_indices().nth(columns).unwrap().0);
+            if !line.is_empty() {
+                lines.push(line);
+            }
+            lines.push(word);
+            line = String::new();
+            word = rest;
+        }
+
+        if line.is_empty() {
+            line = word;
+        } else if line.chars().count() + 1 + word.chars().count() <= columns {
+            line.push(' ');
+            line.push_str(&word);
+        } else {
+            lines.push(line);
+            line = word;
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
@@ -0,0 +1,45 @@
+extern crate chipper;
+
+use chipper::buzzer::{Buzzer, SAMPLES_PER_FRAME};
+use chipper::cpu::Chip8;
+
+// 200: 60NN  V0 = NN
+// 202: F018  sound timer = V0
+// 204: 1204  jump to 204
+fn beep(frames: u8) -> Chip8 {
+    let mut cpu = Chip8::new();
+    cpu.load_rom(&[0x60, frames, 0xF0, 0x18, 0x12, 0x04]);
+    cpu
+}
+
+// Samples of tone, counting whole frames with sound in them
+fn tone_length(frames: u8) -> usize {
+    let mut cpu = beep(frames);
+    let mut buzzer = Buzzer::new();
+    let mut length = 0;
+    let mut silent = false;
+    for _ in 0..frames as usize + 10 {
+        let samples = buzzer.run_frame(&mut cpu, 10).unwrap();
+        assert_eq!(samples.len(), SAMPLES_PER_FRAME);
+        if samples.iter().any(|&sample| sample != 0) {
+            assert!(!silent, "the tone came back");
+            length += samples.len();
+        } else if length > 0 {
+            silent = true;
+        }
+    }
+
+    length
+}
+
+#[test]
+fn tone_lasts_as_many_frames_as_the_sound_timer() {
+    assert_eq!(tone_length(1), SAMPLES_PER_FRAME);
+    assert_eq!(tone_length(2), 2 * SAMPLES_PER_FRAME);
+    assert_eq!(tone_length(30), 30 * SAMPLES_PER_FRAME);
+}
+
+#[test]
+fn no_tone_without_the_sound_timer() {
+    assert_eq!(tone_length(0), 0);
+}
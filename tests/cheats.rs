@@ -0,0 +1,78 @@
+extern crate chipper;
+
+use chipper::cheats::{Cheats, Filter, Search};
+use chipper::cpu::Chip8;
+
+// Counts up at 0x300, reading the counter back from memory every time:
+//
+// 200: A300  I = 300
+// 202: F065  V0 = [300]
+// 204: 7001  V0 += 1
+// 206: F055  [300] = V0
+// 208: 1200  jump to 200
+//
+// The loop is 5 instructions, so each frame of 10 counts up by 2
+const COUNTER: [u8; 10] = [0xA3, 0x00, 0xF0, 0x65, 0x70, 0x01, 0xF0, 0x55, 0x12, 0x00];
+const CYCLES_PER_FRAME: usize = 10;
+
+fn counter() -> Chip8 {
+    let mut cpu = Chip8::new();
+    cpu.load_rom(&COUNTER);
+    cpu
+}
+
+#[test]
+fn search_narrows_down_to_the_counter() {
+    let mut cpu = counter();
+    let mut search = Search::new(cpu.memory());
+    assert_eq!(search.candidates().len(), cpu.memory().len());
+
+    // Nothing changes while the program isn't running
+    assert_eq!(search.narrow(cpu.memory(), Filter::Unchanged), cpu.memory().len());
+
+    cpu.run_frame(CYCLES_PER_FRAME).unwrap();
+    assert_eq!(search.narrow(cpu.memory(), Filter::Increased), 1);
+    assert_eq!(search.candidates(), &[0x300]);
+
+    cpu.run_frame(CYCLES_PER_FRAME).unwrap();
+    assert_eq!(search.narrow(cpu.memory(), Filter::Decreased), 0);
+
+    let mut search = Search::new(cpu.memory());
+    assert_eq!(search.narrow(cpu.memory(), Filter::Equal(4)), 1);
+    cpu.run_frame(CYCLES_PER_FRAME).unwrap();
+    assert_eq!(search.narrow(cpu.memory(), Filter::Changed), 1);
+    assert_eq!(search.candidates(), &[0x300]);
+}
+
+#[test]
+fn frozen_value_is_written_before_every_frame() {
+    let mut cpu = counter();
+    let mut cheats = Cheats::new();
+    cheats.freeze(0x300, 100);
+    cheats.freeze(0x301, 7);
+    assert!(!cheats.unfreeze(0x302));
+    assert!(cheats.unfreeze(0x301));
+    assert_eq!(cheats.iter().collect::<Vec<_>>(), vec![(0x300, 100)]);
+
+    for _ in 0..10 {
+        cheats.apply(&mut cpu);
+        cpu.run_frame(CYCLES_PER_FRAME).unwrap();
+        assert_eq!(cpu.memory()[0x300], 102);
+    }
+}
+
+#[test]
+fn frozen_instruction_is_decoded_again() {
+    let mut cpu = counter();
+    cpu.run_frame(CYCLES_PER_FRAME).unwrap();
+    assert_eq!(cpu.memory()[0x300], 2);
+
+    // 7001 becomes 7002 once the loop has already run
+    let mut cheats = Cheats::new();
+    cheats.freeze(0x205, 2);
+    for _ in 0..3 {
+        cheats.apply(&mut cpu);
+        cpu.run_frame(CYCLES_PER_FRAME).unwrap();
+    }
+    assert_eq!(cpu.memory()[0x300], 2 + 3 * 4);
+}
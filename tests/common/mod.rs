@@ -0,0 +1,18 @@
+// Helpers shared by the integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+pub fn rom_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("roms").join(name)
+}
+
+// The contents of a ROM in roms/
+pub fn rom(name: &str) -> Vec<u8> {
+    let mut rom = Vec::new();
+    File::open(rom_path(name)).and_then(|mut f| f.read_to_end(&mut rom)).unwrap();
+
+    rom
+}
@@ -0,0 +1,489 @@
+// Programs run for a fixed number of frames with scripted input, and the screen they leave
+// behind is compared with a golden snapshot in tests/golden.
+//
+// The test programs are assembled below, they are not the well-known third-party test ROMs.
+// Each one draws its results as hex digits, which are read back from the screen and compared
+// with the results the CHIP-8 instructions are documented to give: a 1 is a passed check.
+// The snapshots of the ROMs from roms/ only pin down the current behaviour, to catch
+// regressions in real games.
+//
+// After an intended change in behaviour, regenerate the snapshots with
+// UPDATE_GOLDEN=1 cargo test --test conformance
+// and review the differences.
+extern crate chipper;
+
+mod common;
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use chipper::font::FONTSET;
+use chipper::cpu::{Chip8, Quirks, CYCLES_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH,
+                   VIP_STACK_DEPTH};
+
+// Free memory for the programs to use
+const SCRATCH: u16 = 0xE00;
+
+// Where the programs draw their digits
+const ADVANCE: usize = 5;
+const LINE_HEIGHT: usize = 7;
+
+// Platform quirks, the same as in the ROM database
+fn original_chip8() -> Quirks {
+    Quirks {
+        shift: false,
+        memory_increment_by_x: false,
+        memory_leave_i_unchanged: false,
+        jump: false,
+        logic: true,
+        wrap: false,
+    }
+}
+
+fn chip48() -> Quirks {
+    Quirks {
+        shift: true,
+        memory_increment_by_x: true,
+        memory_leave_i_unchanged: false,
+        jump: true,
+        logic: false,
+        wrap: false,
+    }
+}
+
+fn superchip() -> Quirks {
+    Quirks {
+        shift: true,
+        memory_increment_by_x: false,
+        memory_leave_i_unchanged: true,
+        jump: true,
+        logic: false,
+        wrap: false,
+    }
+}
+
+// Key presses and releases: (frame, key, pressed)
+type Script<'a> = &'a [(usize, usize, bool)];
+
+// Run a ROM from a fresh machine and return its screen
+fn run(rom: &[u8], quirks: Quirks, frames: usize, script: Script) -> String {
+    let mut cpu = Chip8::new();
+    cpu.quirks = quirks;
+    cpu.stack_depth = VIP_STACK_DEPTH;
+    cpu.seed(1);
+    cpu.load_rom(rom);
+
+    for frame in 0..frames {
+        for &(at, key, pressed) in script {
+            if at == frame && pressed {
+                cpu.key_down(key);
+            } else if at == frame {
+                cpu.key_up(key);
+            }
+        }
+
+        if let Err(fault) = cpu.run_frame(CYCLES_PER_FRAME) {
+            panic!("{} at {:03X} in frame {}", fault, cpu.pc(), frame);
+        }
+    }
+
+    screen(&cpu)
+}
+
+fn screen(cpu: &Chip8) -> String {
+    let mut screen = String::new();
+    for y in 0..SCREEN_HEIGHT {
+        screen.extend((0..SCREEN_WIDTH).map(|x| if cpu.gfx.get(x, y) { '#' } else { '.' }));
+        screen.push('\n');
+    }
+
+    screen
+}
+
+fn check(name: &str, screen: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.txt", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        File::create(&path).and_then(|mut f| f.write_all(screen.as_bytes())).unwrap();
+        return;
+    }
+
+    let mut golden = String::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_string(&mut golden))
+        .unwrap_or_else(|e| {
+            panic!("{}: {} (run with UPDATE_GOLDEN=1 to create it)", path.display(), e)
+        });
+
+    assert!(golden == screen, "{} differs from {}:\n{}", name, path.display(), screen);
+}
+
+// The digits a test program drew, one string per line of text
+fn digits(screen: &str) -> Vec<String> {
+    let pixels: Vec<&[u8]> = screen.lines().map(str::as_bytes).collect();
+    let lit = |x: usize, y: usize| x < SCREEN_WIDTH && y < SCREEN_HEIGHT && pixels[y][x] == b'#';
+    let glyph = |x: usize, y: usize| -> Vec<u8> {
+        (y..y + 5).map(|y| (x..x + 4).fold(0, |bits, x| bits << 1 | lit(x, y) as u8) << 4).collect()
+    };
+
+    let mut lines = Vec::new();
+    for y in (0..SCREEN_HEIGHT - 4).step_by(LINE_HEIGHT) {
+        let mut line = String::new();
+        let mut x = 0;
+        while x < SCREEN_WIDTH {
+            match FONTSET.chunks(5).position(|digit| digit == &glyph(x, y)[..]) {
+                Some(digit) => {
+                    line.push_str(&format!("{:X}", digit));
+                    x += ADVANCE;
+                }
+                None => x += 1,
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+    }
+
+    lines
+}
+
+// Test programs are written as lists of opcodes.
+// Results are drawn with the font at a cursor kept in VA/VB, which also clobbers V9 and VF.
+struct Program {
+    ops: Vec<u16>,
+}
+
+impl Program {
+    fn new() -> Program {
+        let mut program = Program { ops: Vec::new() };
+        program.op(0x6A00).op(0x6B00);
+
+        program
+    }
+
+    fn op(&mut self, op: u16) -> &mut Program {
+        self.ops.push(op);
+        self
+    }
+
+    // Address of the next instruction
+    fn next(&self) -> u16 {
+        0x200 + self.ops.len() as u16 * 2
+    }
+
+    // Draw the lowest digit of VX and move the cursor right, using V9 for the digit
+    fn show(&mut self, x: u16) -> &mut Program {
+        self.op(0x690F).op(0x8902 | x << 4).op(0xF929).op(0xDAB5).op(0x7A05)
+    }
+
+    fn newline(&mut self) -> &mut Program {
+        self.op(0x6A00).op(0x7B07)
+    }
+
+    // Draw 1 if `skip` skips the next instruction, 0 if it doesn't
+    fn check(&mut self, skip: u16) -> &mut Program {
+        self.op(0x6E01).op(skip).op(0x6E00).show(0xE)
+    }
+
+    // Draw the lowest digit of V0 and the flag
+    fn show_result(&mut self) -> &mut Program {
+        self.op(0x81F0).show(0x0).show(0x1).op(0x7A04)
+    }
+
+    // End with an endless loop
+    fn assemble(&mut self) -> Vec<u8> {
+        let end = self.next();
+        self.op(0x1000 | end);
+
+        self.ops.iter().flat_map(|op| vec![(op >> 8) as u8, *op as u8]).collect()
+    }
+}
+
+// One check per instruction, every digit should be 1
+fn opcodes() -> Vec<u8> {
+    let mut p = Program::new();
+
+    // 00E0: anything drawn before is cleared
+    p.op(0xA000).op(0xD005).op(0x00E0);
+
+    // Skips: 3XNN, 4XNN, 5XY0, 9XY0
+    p.op(0x6005).check(0x3005).check(0x4006);
+    p.op(0x6105).check(0x5010);
+    p.op(0x6106).check(0x9010);
+    // 7XNN wraps around and leaves VF alone
+    p.op(0x6F07).op(0x60FF).op(0x7002).check(0x3F07).check(0x3001);
+    p.newline();
+
+    // 8XY0 - 8XY3
+    p.op(0x6107).op(0x8010).check(0x3007);
+    p.op(0x600C).op(0x610A).op(0x8011).check(0x300E);
+    p.op(0x600C).op(0x8012).check(0x3008);
+    p.op(0x600C).op(0x8013).check(0x3006);
+    // CXNN with a mask of 0
+    p.op(0xC000).check(0x3000);
+    // FX15/FX07, the timer may have counted down in between
+    p.op(0x6009).op(0xF015).op(0x6000).op(0xF007).check(0x4000);
+    p.newline();
+
+    // 1NNN
+    p.op(0x6E00);
+    let jump = p.next();
+    p.op(0x1000 | (jump + 4)).op(0x1000 | (jump + 6)).op(0x6E01).show(0xE);
+    // 2NNN/00EE
+    let call = p.next();
+    p.op(0x1000 | (call + 6)).op(0x6E01).op(0x00EE);
+    p.op(0x6E00).op(0x2000 | (call + 2)).show(0xE);
+    // BNNN, the jump lands two bytes further on
+    p.op(0x6E00).op(0x6002);
+    let jump = p.next();
+    p.op(0xB000 | (jump + 2)).op(0x1000 | (jump + 6)).op(0x6E01).show(0xE);
+    // FX29 points at the font, whose 0 starts with F0
+    p.op(0x6000).op(0xF029).op(0xF065).check(0x30F0);
+    // DXYN sets VF on collision only, drawing twice leaves no trace
+    p.op(0x6038).op(0x611A).op(0xA000);
+    p.op(0xD011).op(0x8DF0).op(0xD011).op(0x8CF0).check(0x3D00).check(0x3C01);
+    p.newline();
+
+    // FX33
+    p.op(0x607B).op(0xA000 | SCRATCH).op(0xF033).op(0xF265);
+    p.check(0x3001).check(0x3102).check(0x3203);
+    // FX55/FX65
+    p.op(0x6004).op(0x6105).op(0x6206).op(0xA000 | SCRATCH).op(0xF255);
+    p.op(0x6000).op(0x6100).op(0x6200).op(0xA000 | SCRATCH).op(0xF265);
+    p.check(0x3004).check(0x3105).check(0x3206);
+    // FX1E
+    p.op(0xA000 | SCRATCH).op(0x6002).op(0xF01E).op(0xF065).check(0x3006);
+
+    p.assemble()
+}
+
+// Result digit and flag of the arithmetic instructions
+fn flags() -> Vec<u8> {
+    let mut p = Program::new();
+
+    // 8XY4: 5 + 3 = 8, FF + 3 = 2 with carry
+    p.op(0x6005).op(0x6303).op(0x8034).show_result();
+    p.op(0x60FF).op(0x6303).op(0x8034).show_result();
+    // 8XY5: 7 - 3 = 4 without borrow, 3 - 7 = FC with borrow
+    p.op(0x6007).op(0x6303).op(0x8035).show_result();
+    p.op(0x6003).op(0x6307).op(0x8035).show_result();
+    p.newline();
+
+    // 8XY7: 7 - 3 = 4 without borrow, 3 - 7 = FC with borrow
+    p.op(0x6003).op(0x6307).op(0x8037).show_result();
+    p.op(0x6007).op(0x6303).op(0x8037).show_result();
+    // 8XY6: 05 >> 1 = 2 shifting out 1, 04 >> 1 = 2 shifting out 0
+    p.op(0x6005).op(0x6305).op(0x8036).show_result();
+    p.op(0x6004).op(0x6304).op(0x8036).show_result();
+    p.newline();
+
+    // 8XYE: 81 << 1 = 02 shifting out 1, 41 << 1 = 82 shifting out 0
+    p.op(0x6081).op(0x6381).op(0x803E).show_result();
+    p.op(0x6041).op(0x6341).op(0x803E).show_result();
+    p.newline();
+
+    // With VF as VX, the flag wins over the result
+    p.op(0x6FFF).op(0x6301).op(0x8F34).op(0x81F0).show(0x1);
+    p.op(0x6F07).op(0x6303).op(0x8F35).op(0x81F0).show(0x1);
+    p.op(0x6F03).op(0x6307).op(0x8F37).op(0x81F0).show(0x1);
+    p.op(0x6F05).op(0x6305).op(0x8F36).op(0x81F0).show(0x1);
+    p.op(0x6F81).op(0x6381).op(0x8F3E).op(0x81F0).show(0x1);
+
+    p.assemble()
+}
+
+// Results of the instructions that behave differently between platforms
+fn quirks() -> Vec<u8> {
+    let mut p = Program::new();
+
+    // Shift: 8XY6 shifts VY (10 >> 1 = 8) or VX (4 >> 1 = 2)
+    p.op(0x6004).op(0x6310).op(0x8036).show(0x0).op(0x7A04);
+    // Logic: 8XY1 leaves VF (5) or resets it (0)
+    p.op(0x6F05).op(0x8031).op(0x81F0).show(0x1).op(0x7A04);
+    // Memory: FX55 leaves I alone (920), increases it by X (190) or by X + 1 (129)
+    p.op(0x6001).op(0x6102).op(0x6200).op(0xA000 | SCRATCH).op(0xF155);
+    p.op(0x6009).op(0xF055).op(0xA000 | SCRATCH).op(0xF265);
+    p.show(0x0).show(0x1).show(0x2).op(0x7A04);
+    // Jump: BNNN adds V0 (1) or V2 (0)
+    p.op(0x6E00).op(0x6002).op(0x6200);
+    let jump = p.next();
+    p.op(0xB000 | (jump + 2)).op(0x1000 | (jump + 6)).op(0x6E01).show(0xE);
+
+    p.assemble()
+}
+
+// An instruction changed by the program after it already ran once. FX55 writes only its second
+// byte, turning 6E00 into 6E01, so the second run has to set VE to 1.
+fn self_modifying() -> Vec<u8> {
+    let mut p = Program::new();
+
+    let modify = p.next() + 4;
+    let target = modify + 10;
+    p.op(0x6200).op(0x1000 | target);
+    p.op(0x6201).op(0xA000 | (target + 1)).op(0x6001).op(0xF055).op(0x1000 | target);
+    p.op(0x6E00).op(0x3201).op(0x1000 | modify).show(0xE);
+
+    p.assemble()
+}
+
+// A box drawn across the bottom right corner, and one at a position past the edges of the screen
+fn sprites() -> Vec<u8> {
+    let mut p = Program::new();
+
+    p.op(0x60FF).op(0x6181).op(0x6281).op(0x6381).op(0x64FF);
+    p.op(0xA000 | SCRATCH).op(0xF455).op(0xA000 | SCRATCH);
+    p.op(0x603C).op(0x611C).op(0xD015);
+    p.op(0x6054).op(0x612A).op(0xD015);
+
+    p.assemble()
+}
+
+// The last key FX0A returned
+fn keypad_wait() -> Vec<u8> {
+    let mut p = Program::new();
+
+    let wait = p.next();
+    p.op(0xF00A).op(0x00E0).op(0x6A00).show(0x0).op(0x1000 | wait);
+
+    p.assemble()
+}
+
+// Whether keys 5 and 6 are held after half a second, read with EX9E and EXA1
+fn keypad_held() -> Vec<u8> {
+    let mut p = Program::new();
+
+    p.op(0x601E).op(0xF015);
+    let wait = p.next();
+    p.op(0xF007).op(0x3000).op(0x1000 | wait);
+
+    for &key in &[0x5, 0x6] {
+        p.op(0x6000 | key);
+        p.op(0x6E00).op(0xE09E);
+        let skip = p.next() + 4;
+        p.op(0x1000 | skip).op(0x6E01).show(0xE);
+        p.op(0x6E00).op(0xE0A1).op(0x6E01).show(0xE);
+    }
+
+    p.assemble()
+}
+
+#[test]
+fn opcode_test() {
+    let screen = run(&opcodes(), original_chip8(), 60, &[]);
+    assert_eq!(digits(&screen), ["111111", "111111", "111111", "1111111"]);
+    check("opcodes", &screen);
+}
+
+#[test]
+fn flags_test() {
+    let screen = run(&flags(), original_chip8(), 60, &[]);
+    assert_eq!(digits(&screen), ["802141C0", "41C02120", "2120", "11111"]);
+    check("flags", &screen);
+}
+
+// Shift, logic, memory and jump results as listed in quirks()
+#[test]
+fn quirks_test() {
+    let platforms = [("quirks-default", Quirks::default(), "859201"),
+                     ("quirks-chip8", original_chip8(), "801291"),
+                     ("quirks-chip48", chip48(), "251900"),
+                     ("quirks-superchip", superchip(), "259200")];
+    for &(name, platform, expected) in platforms.iter() {
+        let screen = run(&quirks(), platform, 60, &[]);
+        assert_eq!(digits(&screen), [expected], "{}", name);
+        check(name, &screen);
+    }
+}
+
+#[test]
+fn self_modifying_test() {
+    let screen = run(&self_modifying(), original_chip8(), 10, &[]);
+    assert_eq!(digits(&screen), ["1"]);
+    check("self-modifying", &screen);
+}
+
+#[test]
+fn sprites_test() {
+    let clip = original_chip8();
+    let wrap = Quirks { wrap: true, ..clip };
+    check("sprites-clip", &run(&sprites(), clip, 10, &[]));
+    check("sprites-wrap", &run(&sprites(), wrap, 10, &[]));
+}
+
+#[test]
+fn keypad_test() {
+    let script = [(10, 0x7, true), (15, 0x7, false), (30, 0xA, true), (35, 0xA, false)];
+    let screen = run(&keypad_wait(), original_chip8(), 60, &script);
+    assert_eq!(digits(&screen), ["A"]);
+    check("keypad-wait", &screen);
+
+    // FX0A returns on the release, once for two keys released together
+    let script = [(10, 0x7, true), (10, 0xA, true), (20, 0x7, false), (20, 0xA, false)];
+    let screen = run(&keypad_wait(), original_chip8(), 60, &script);
+    assert_eq!(digits(&screen), ["7"]);
+    check("keypad-wait-two", &screen);
+    let script = [(10, 0x7, true)];
+    assert!(!run(&keypad_wait(), original_chip8(), 60, &script).contains('#'));
+
+    // 5 is held: EX9E skips and EXA1 doesn't. 6 was released: the other way round.
+    let script = [(5, 0x5, true), (10, 0x6, true), (20, 0x6, false)];
+    let screen = run(&keypad_held(), original_chip8(), 60, &script);
+    assert_eq!(digits(&screen), ["1100"]);
+    check("keypad-held", &screen);
+}
+
+#[test]
+fn brix() {
+    let script = [(100, 0x4, true), (160, 0x4, false), (200, 0x6, true), (300, 0x6, false)];
+    check("BRIX", &run(&common::rom("BRIX"), original_chip8(), 600, &script));
+}
+
+#[test]
+fn invaders() {
+    let script = [(60, 0x5, true),
+                  (70, 0x5, false),
+                  (150, 0x4, true),
+                  (200, 0x4, false),
+                  (250, 0x5, true),
+                  (260, 0x5, false)];
+    check("INVADERS", &run(&common::rom("INVADERS"), original_chip8(), 600, &script));
+}
+
+#[test]
+fn maze() {
+    check("MAZE", &run(&common::rom("MAZE"), original_chip8(), 300, &[]));
+}
+
+#[test]
+fn pong2() {
+    let script = [(100, 0x1, true), (200, 0x1, false), (300, 0xC, true), (400, 0xC, false)];
+    check("PONG2", &run(&common::rom("PONG2"), original_chip8(), 600, &script));
+}
+
+#[test]
+fn tetris() {
+    let script = [(100, 0x4, true), (110, 0x4, false), (200, 0x5, true), (210, 0x5, false)];
+    check("TETRIS", &run(&common::rom("TETRIS"), original_chip8(), 600, &script));
+}
+
+#[test]
+fn vers() {
+    // Both light cycles run into the border, which is drawn up to the edges of the screen
+    check("VERS", &run(&common::rom("VERS"), original_chip8(), 200, &[]));
+}
+
+#[test]
+fn kaleid() {
+    // Every press and release draws one step
+    let mut script = Vec::new();
+    for (tap, &key) in [0x6, 0x6, 0x6, 0x6, 0x8, 0x8, 0x8, 0x4].iter().enumerate() {
+        script.push((30 + tap * 10, key, true));
+        script.push((35 + tap * 10, key, false));
+    }
+    check("KALEID", &run(&common::rom("KALEID"), original_chip8(), 300, &script));
+}
+
@@ -0,0 +1,61 @@
+extern crate chipper;
+
+use chipper::cpu::{Chip8, SCREEN_HEIGHT, SCREEN_WIDTH};
+use chipper::framebuffer::Framebuffer;
+use chipper::palette::Palette;
+
+#[test]
+fn xor_and_collision() {
+    let mut gfx = Framebuffer::new();
+    assert!(!gfx.xor_row(3, 0xF << 60));
+    assert!(gfx.get(0, 3) && gfx.get(3, 3) && !gfx.get(4, 3));
+    assert_eq!(gfx.row(3), 0xF000_0000_0000_0000);
+
+    assert!(gfx.xor_row(3, 0x1 << 60));
+    assert!(!gfx.get(3, 3));
+    assert_eq!(gfx.pixels().filter(|&pixel| pixel).count(), 3);
+}
+
+#[test]
+fn dirty_rows() {
+    let mut gfx = Framebuffer::new();
+    assert_eq!(gfx.dirty_rows().count(), SCREEN_HEIGHT);
+    gfx.clear_dirty();
+    assert!(!gfx.is_dirty());
+
+    gfx.xor_row(5, 1);
+    gfx.xor_row(9, 0);
+    assert_eq!(gfx.dirty_rows().collect::<Vec<_>>(), vec![5]);
+
+    // Clearing only touches rows that had something on them
+    gfx.clear_dirty();
+    gfx.clear();
+    assert_eq!(gfx.dirty_rows().collect::<Vec<_>>(), vec![5]);
+    gfx.clear_dirty();
+    gfx.clear();
+    assert!(!gfx.is_dirty());
+}
+
+#[test]
+fn sprite_marks_its_rows() {
+    // Digit 0 at (8, 10)
+    let rom = [0x60, 0x08, 0x61, 0x0A, 0xA0, 0x00, 0xD0, 0x15];
+    let mut cpu = Chip8::new();
+    cpu.load_rom(&rom);
+    cpu.gfx.clear_dirty();
+    cpu.run_frame(4).unwrap();
+
+    assert_eq!(cpu.gfx.dirty_rows().collect::<Vec<_>>(), vec![10, 11, 12, 13, 14]);
+    assert_eq!(cpu.gfx.row(10), 0xF0 << 48);
+}
+
+#[test]
+fn rgba() {
+    let palette = Palette::theme("mono").unwrap();
+    let mut gfx = Framebuffer::new();
+    gfx.xor_row(0, 1 << 63);
+
+    let rgba = gfx.to_rgba(&palette);
+    assert_eq!(rgba.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 4);
+    assert_eq!(&rgba[..8], &[255, 255, 255, 255, 0, 0, 0, 255]);
+}
@@ -0,0 +1,143 @@
+extern crate chipper;
+
+mod common;
+
+use chipper::cpu::Fault;
+use chipper::gym::{self, Environment, Number, Rules};
+
+fn brix() -> Environment {
+    let rom = common::rom("BRIX");
+    let mut environment = Environment::new(&rom, gym::rules("BRIX").unwrap()).unwrap();
+    environment.cpu.seed(1);
+    environment
+}
+
+// Play with `action` until the episode ends, returning the total reward and the frames played
+fn episode(environment: &mut Environment, action: usize) -> (f32, u64) {
+    let mut total = 0.0;
+    loop {
+        let step = environment.step(action);
+        total += step.reward;
+        if step.done {
+            return (total, step.info.frame);
+        }
+    }
+}
+
+#[test]
+fn brix_episode() {
+    let mut environment = brix();
+    assert_eq!(environment.action_count(), 3);
+    let first = environment.reset();
+    assert_eq!(first.len(), 64 * 32);
+
+    // Without moving the paddle every ball is lost, after hitting a few bricks
+    let (reward, frames) = episode(&mut environment, 0);
+    assert_eq!(reward, 12.0);
+    assert_eq!(frames % 4, 0);
+
+    let step = environment.step(1);
+    assert!(step.done);
+    assert_eq!(step.reward, 0.0);
+    assert_eq!(step.info.score, Some(12));
+    assert_eq!(step.info.frame, frames);
+
+    // Episodes are repeatable with a seeded machine
+    assert_eq!(environment.reset(), first);
+    assert_eq!(episode(&mut environment, 0), (reward, frames));
+}
+
+#[test]
+fn frame_skip() {
+    let mut environment = brix();
+    environment.frame_skip = 1;
+    environment.reset();
+    assert_eq!(environment.step(2).info.frame, 1);
+    assert_eq!(environment.step(2).info.frame, 2);
+}
+
+#[test]
+fn episodes_end_without_lives() {
+    // Counts down from 2 at 0x300: A300 6002 F055 70FF 1204
+    let rom = [0xA3, 0x00, 0x60, 0x02, 0xF0, 0x55, 0x70, 0xFF, 0x12, 0x04];
+    let rules = Rules {
+        lives: Some(Number::Byte(0x300)),
+        end_on_halt: false,
+        ..Rules::default()
+    };
+    let mut environment = Environment::new(&rom, rules).unwrap();
+    environment.frame_skip = 1;
+    environment.cycles_per_frame = 3;
+    environment.reset();
+
+    for &lives in &[2, 1] {
+        let step = environment.step(0);
+        assert_eq!((step.info.lives, step.done), (Some(lives), false));
+    }
+    let step = environment.step(0);
+    assert_eq!((step.info.lives, step.done), (Some(0), true));
+}
+
+#[test]
+fn episodes_end_on_halts_and_faults() {
+    let mut environment = Environment::new(&[0x12, 0x00], Rules::default()).unwrap();
+    assert_eq!(environment.action_count(), 17);
+    environment.reset();
+    assert!(environment.step(0).done);
+
+    let mut environment = Environment::new(&[0xFF, 0xFF], Rules::default()).unwrap();
+    environment.reset();
+    let step = environment.step(16);
+    assert!(step.done);
+    assert_eq!(step.info.fault, Some(Fault::UnknownOpcode(0xFFFF)));
+    assert_eq!(step.info.frame, 0);
+}
+
+#[test]
+fn rules_have_to_fit_the_machine() {
+    let new = |rules| Environment::new(&[0x12, 0x00], rules).map(|_| ());
+    assert_eq!(new(Rules { actions: vec![], ..Rules::default() }),
+               Err("there has to be at least one action".to_string()));
+    assert!(new(Rules { actions: vec![vec![], vec![16]], ..Rules::default() }).is_err());
+
+    let score = |score| new(Rules { score: Some(score), ..Rules::default() });
+    assert_eq!(score(Number::Byte(0xFFF)), Ok(()));
+    assert!(score(Number::Byte(0x1000)).is_err());
+    assert_eq!(score(Number::Bcd(0xFFD, 3)), Ok(()));
+    assert!(score(Number::Bcd(0xFFE, 3)).is_err());
+    assert!(score(Number::Bcd(0x300, 0)).is_err());
+    assert!(score(Number::Bcd(0x300, 10)).is_err());
+    assert!(new(Rules { lives: Some(Number::Byte(0xFFFF)), ..Rules::default() }).is_err());
+}
+
+#[test]
+fn rules_files() {
+    let json = r#"{
+        "COUNTER": {
+            "actions": [[], [1, 2]],
+            "score": {"address": "0x300", "digits": 2},
+            "lives": {"address": "302"}
+        },
+        "ANY": {}
+    }"#;
+    let rules = gym::parse_rules(json).unwrap();
+    assert_eq!(rules["COUNTER"],
+               Rules {
+                   actions: vec![vec![], vec![1, 2]],
+                   score: Some(Number::Bcd(0x300, 2)),
+                   lives: Some(Number::Byte(0x302)),
+                   end_on_halt: true,
+               });
+    assert_eq!(rules["ANY"], Rules::default());
+
+    // Broken files name the game and what's wrong with it
+    let error = |json| gym::parse_rules(json).unwrap_err();
+    assert_eq!(error(r#"{"A": {"score": {"address": "1000"}}}"#),
+               "A: score at 1000 goes past the end of memory");
+    assert_eq!(error(r#"{"A": {"lives": {"address": 3}}}"#), "A: lives has to have a hex address");
+    assert_eq!(error(r#"{"A": {"actions": [[17]]}}"#),
+               "A: action 0 holds key 17, keys go from 0 to 15");
+    assert_eq!(error(r#"{"A": {"speed": 1}}"#), "A: unknown field speed");
+    assert!(gym::parse_rules("[]").is_err());
+    assert!(gym::read_rules("no such file").is_err());
+}
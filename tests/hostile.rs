@@ -0,0 +1,122 @@
+// Programs that used to crash the interpreter. They have to run or stop with a fault, not panic.
+// The fuzz targets in fuzz/ look for more of these.
+extern crate chipper;
+
+use chipper::cpu::{Chip8, Fault};
+
+fn run(program: &[u16], frames: usize) -> (Chip8, Result<(), Fault>) {
+    run_with(Chip8::new(), program, frames)
+}
+
+fn run_with(mut cpu: Chip8, program: &[u16], frames: usize) -> (Chip8, Result<(), Fault>) {
+    let rom: Vec<u8> = program.iter().flat_map(|op| vec![(op >> 8) as u8, *op as u8]).collect();
+    cpu.load_rom(&rom);
+
+    for _ in 0..frames {
+        if let Err(fault) = cpu.run_frame(10) {
+            return (cpu, Err(fault));
+        }
+    }
+    (cpu, Ok(()))
+}
+
+#[test]
+fn return_with_empty_stack() {
+    let (_, result) = run(&[0x00EE], 1);
+    assert_eq!(result, Err(Fault::StackUnderflow));
+}
+
+#[test]
+fn index_register_overflow() {
+    // I = 0xFFF, then V0 = 0xFF added to it forever
+    let (_, result) = run(&[0xAFFF, 0x60FF, 0xF01E, 0x1204], 1000);
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn index_register_overflow_on_load() {
+    let mut cpu = Chip8::new();
+    cpu.quirks.memory_leave_i_unchanged = false;
+    let (_, result) = run_with(cpu, &[0xAFFF, 0xFF65, 0x1202], 1000);
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn key_register_out_of_range() {
+    let (_, result) = run(&[0x60FF, 0xE09E, 0xE0A1, 0x1202], 1);
+    assert_eq!(result, Ok(()));
+}
+
+#[test]
+fn sprite_past_the_bottom_of_the_screen() {
+    let (cpu, result) = run(&[0x60FF, 0xA000, 0xD00F, 0x1206], 1);
+    assert_eq!(result, Ok(()));
+    assert!(cpu.gfx.pixels().any(|pixel| pixel));
+}
+
+#[test]
+fn program_counter_runs_off_the_end() {
+    // Write a jump back to 0x200 at address 0, then run the last instruction in memory
+    let mut program = vec![0; 0x700];
+    program[..5].copy_from_slice(&[0xA000, 0x6012, 0x6100, 0xF155, 0x1FFE]);
+    program[0x6FF] = 0x6200;
+
+    let (cpu, result) = run(&program, 1000);
+    assert_eq!(result, Ok(()));
+    assert!(cpu.pc() < 0x1000);
+}
+
+// A subroutine that calls itself until the stack is full
+#[test]
+fn stack_overflow() {
+    for &depth in [12, 16].iter() {
+        let mut cpu = Chip8::new();
+        cpu.stack_depth = depth;
+        let (cpu, result) = run_with(cpu, &[0x2200], 10);
+        assert_eq!(result, Err(Fault::StackOverflow));
+        assert_eq!(cpu.stack().len(), depth);
+    }
+}
+
+fn without_wrapping() -> Chip8 {
+    let mut cpu = Chip8::new();
+    cpu.wrap_addresses = false;
+    cpu
+}
+
+#[test]
+fn store_past_the_end_of_memory() {
+    // V0 and V1 into 0xFFF and 0x1000
+    let (cpu, result) = run_with(without_wrapping(), &[0x6001, 0xAFFF, 0xF155], 1);
+    assert_eq!(result, Err(Fault::AddressOutOfRange(0x1000)));
+    assert_eq!(cpu.memory()[0xFFF], 0);
+
+    let (cpu, result) = run(&[0x6001, 0xAFFF, 0xF155, 0x1206], 1);
+    assert_eq!(result, Ok(()));
+    assert_eq!(cpu.memory()[0xFFF], 1);
+}
+
+#[test]
+fn load_past_the_end_of_memory() {
+    let (_, result) = run_with(without_wrapping(), &[0xAFFF, 0xF165], 1);
+    assert_eq!(result, Err(Fault::AddressOutOfRange(0x1000)));
+}
+
+#[test]
+fn sprite_past_the_end_of_memory() {
+    // Two lines fit, the third one is past the end
+    let (cpu, result) = run_with(without_wrapping(), &[0xAFFE, 0xD002, 0xD003], 1);
+    assert_eq!(result, Err(Fault::AddressOutOfRange(0x1000)));
+    assert_eq!(cpu.pc(), 0x204);
+}
+
+#[test]
+fn program_counter_past_the_end_of_memory() {
+    // A single instruction at 0xFFE, after which the program counter leaves memory
+    let mut cpu = without_wrapping();
+    cpu.poke(0xFFE, 0x60);
+    cpu.poke(0xFFF, 0x00);
+    let (cpu, result) = run_with(cpu, &[0x1FFE], 1);
+    assert_eq!(result, Err(Fault::AddressOutOfRange(0x1000)));
+    assert_eq!(cpu.pc(), 0x1000);
+}
@@ -0,0 +1,179 @@
+extern crate chipper;
+
+mod common;
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chipper::cpu::Chip8;
+use chipper::netplay::{self, Netplay};
+
+// PONG2 waits 96 frames before the game starts
+const FRAMES: u32 = 200;
+
+fn machine(name: &str) -> Chip8 {
+    let mut cpu = Chip8::new();
+    cpu.load_rom(&common::rom(name));
+    cpu
+}
+
+// Play FRAMES frames, holding `key` down during the frames in `held`.
+// Returns the final machine, or the error that ended the session.
+fn play(netplay: Result<Netplay, String>,
+        mut cpu: Chip8,
+        key: usize,
+        held: (u32, u32),
+        poke_at: Option<u32>)
+        -> Result<Chip8, String> {
+    let mut netplay = netplay?;
+    for frame in 0..FRAMES {
+        if frame == held.0 {
+            netplay.key_down(key);
+        } else if frame == held.1 {
+            netplay.key_up(key);
+        }
+        if poke_at == Some(frame) {
+            let value = cpu.memory()[0xFFF];
+            cpu.poke(0xFFF, value.wrapping_add(1));
+        }
+
+        netplay.exchange(&mut cpu)?;
+        cpu.run_frame(10).unwrap();
+    }
+
+    Ok(cpu)
+}
+
+// Host and guest in two threads, connected over loopback
+fn session(host_rom: &str,
+           guest_rom: &str,
+           guest_poke_at: Option<u32>)
+           -> (Result<Chip8, String>, Result<Chip8, String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let mut host_cpu = machine(host_rom);
+    let host = thread::spawn(move || {
+        let netplay = netplay::host(&listener, &mut host_cpu, netplay::DEFAULT_DELAY);
+        play(netplay, host_cpu, 0x1, (100, 130), None)
+    });
+
+    let mut guest_cpu = machine(guest_rom);
+    let netplay = netplay::join(&address, &mut guest_cpu);
+    let guest = play(netplay, guest_cpu, 0xD, (110, 150), guest_poke_at);
+
+    (host.join().unwrap(), guest)
+}
+
+#[test]
+fn lockstep() {
+    let (host, guest) = session("PONG2", "PONG2", None);
+    let (host, guest) = (host.unwrap(), guest.unwrap());
+    assert_eq!(host.save_state(), guest.save_state());
+
+    // Both paddles moved, compared to the same game without keys
+    let mut idle = machine("PONG2");
+    for _ in 0..FRAMES {
+        idle.run_frame(10).unwrap();
+    }
+    let column = |cpu: &Chip8, x| (0..32).map(|y| cpu.gfx.get(x, y)).collect::<Vec<_>>();
+    assert!(column(&idle, 0) != column(&host, 0));
+    assert!(column(&idle, 63) != column(&host, 63));
+}
+
+#[test]
+fn desync_is_detected() {
+    let (host, guest) = session("PONG2", "PONG2", Some(150));
+    assert_eq!(host.err().unwrap(), "Netplay: the games went out of sync at frame 150");
+    assert_eq!(guest.err().unwrap(), "Netplay: the games went out of sync at frame 150");
+}
+
+#[test]
+fn different_roms_are_refused() {
+    let (host, guest) = session("PONG2", "PONG", None);
+    assert_eq!(host.err().unwrap(), "Netplay: the other player loaded a different ROM");
+    assert_eq!(guest.err().unwrap(), "Netplay: the other player loaded a different ROM");
+}
+
+// Pass everything from `from` on to `to`, each chunk `latency` after it arrived
+fn delay_stream(mut from: TcpStream, mut to: TcpStream, latency: Duration) {
+    let (sender, receiver) = mpsc::channel::<(Instant, Vec<u8>)>();
+    thread::spawn(move || {
+        for (due, bytes) in receiver {
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+            if to.write_all(&bytes).is_err() {
+                break;
+            }
+        }
+        let _ = to.shutdown(Shutdown::Write);
+    });
+
+    thread::spawn(move || {
+        let mut buffer = [0; 256];
+        while let Ok(length) = from.read(&mut buffer) {
+            let due = Instant::now() + latency;
+            if length == 0 || sender.send((due, buffer[..length].to_vec())).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+// A connection to `address` that takes `latency` each way
+fn slow_link(address: &str, latency: Duration) -> String {
+    let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_address = proxy.local_addr().unwrap().to_string();
+    let address = address.to_string();
+    thread::spawn(move || {
+        let (client, _) = proxy.accept().unwrap();
+        let server = TcpStream::connect(&address).unwrap();
+        delay_stream(client.try_clone().unwrap(), server.try_clone().unwrap(), latency);
+        delay_stream(server, client, latency);
+    });
+
+    proxy_address
+}
+
+// Frames paced at 60 Hz don't wait for the network when the delay covers the latency
+#[test]
+fn latency_is_hidden_by_the_delay() {
+    const PACED_FRAMES: u32 = 60;
+    let frame_time = Duration::from_millis(16);
+    let latency = Duration::from_millis(50);
+
+    let play = move |mut netplay: Netplay, mut cpu: Chip8, key| {
+        let start = Instant::now();
+        for frame in 0..PACED_FRAMES {
+            if frame == 20 {
+                netplay.key_down(key);
+            }
+            netplay.exchange(&mut cpu).unwrap();
+            cpu.run_frame(10).unwrap();
+            thread::sleep(frame_time);
+        }
+        (cpu, start.elapsed())
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = slow_link(&listener.local_addr().unwrap().to_string(), latency);
+    let mut host_cpu = machine("PONG2");
+    let host = thread::spawn(move || {
+        // 6 frames are about 100 ms, enough for a message to get across
+        let netplay = netplay::host(&listener, &mut host_cpu, 6).unwrap();
+        play(netplay, host_cpu, 0x1)
+    });
+
+    let mut guest_cpu = machine("PONG2");
+    let netplay = netplay::join(&address, &mut guest_cpu).unwrap();
+    let (guest, guest_time) = play(netplay, guest_cpu, 0xD);
+    let (host, host_time) = host.join().unwrap();
+    assert_eq!(host.save_state(), guest.save_state());
+
+    // Waiting for a round trip every frame would take over 6 seconds
+    let limit = frame_time * PACED_FRAMES + latency * 10;
+    assert!(guest_time < limit, "the guest took {:?}", guest_time);
+    assert!(host_time < limit, "the host took {:?}", host_time);
+}
@@ -0,0 +1,56 @@
+extern crate chipper;
+extern crate gif;
+
+use std::env;
+use std::fs::{self, File};
+use std::process;
+
+use chipper::palette;
+use chipper::recording::{GifRecorder, MIN_DELAY};
+
+// A screen with one pixel lit, different for every `n`
+fn screen(n: usize) -> Vec<u8> {
+    let mut gfx = vec![0; 64 * 32];
+    let pixel = n % gfx.len();
+    gfx[pixel] = 1;
+    gfx
+}
+
+// Record the screens as 60 Hz frames and return the delays written to the GIF
+fn record(name: &str, screens: &[Vec<u8>]) -> Vec<u16> {
+    let path = env::temp_dir().join(format!("chipper-{}-{}.gif", name, process::id()));
+    let palette = &palette::themes()[0];
+    let mut recorder = GifRecorder::create(path.to_str().unwrap(), (64, 32), 1, palette).unwrap();
+    for gfx in screens {
+        recorder.capture(gfx, palette).unwrap();
+    }
+    recorder.finish().unwrap();
+
+    let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path).unwrap()).unwrap();
+    let mut delays = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    fs::remove_file(&path).unwrap();
+
+    delays
+}
+
+#[test]
+fn no_delay_is_too_short() {
+    // A new screen every frame, for one second
+    let delays = record("every-frame", &(0..60).map(screen).collect::<Vec<_>>());
+    assert!(delays.iter().all(|&delay| delay >= MIN_DELAY), "{:?}", delays);
+    assert_eq!(delays.iter().sum::<u16>(), 100);
+    assert!(delays.len() >= 30);
+}
+
+#[test]
+fn identical_frames_are_merged() {
+    let delays = record("still", &vec![screen(0); 60]);
+    assert_eq!(delays, [100]);
+
+    // A new screen every 3 frames, 20 times a second
+    let delays = record("every-third", &(0..60).map(|n| screen(n / 3)).collect::<Vec<_>>());
+    assert_eq!(delays, [5; 20]);
+}
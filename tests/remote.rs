@@ -0,0 +1,205 @@
+extern crate chipper;
+#[macro_use]
+extern crate serde_json;
+
+mod common;
+
+#[cfg(unix)]
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::thread;
+use serde_json::Value;
+
+use chipper::cpu::Chip8;
+use chipper::remote::{self, Session};
+
+fn call(session: &mut Session, method: &str, params: Value) -> Value {
+    serde_json::from_str(&session.handle(&request(method, params)).unwrap()).unwrap()
+}
+
+fn request(method: &str, params: Value) -> String {
+    json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}).to_string()
+}
+
+fn result(session: &mut Session, method: &str, params: Value) -> Value {
+    let response = call(session, method, params);
+    assert_eq!(response["error"], Value::Null, "{} failed", method);
+    response["result"].clone()
+}
+
+fn error(session: &mut Session, method: &str, params: Value) -> i64 {
+    call(session, method, params)["error"]["code"].as_i64().unwrap()
+}
+
+fn session() -> Session {
+    let mut session = Session::new(Chip8::new(), 10, remote::load_rom);
+    result(&mut session, "load", json!({"path": common::rom_path("BRIX").to_string_lossy()}));
+    session
+}
+
+#[test]
+fn run_and_inspect() {
+    let mut session = session();
+    let rom = result(&mut session, "peek", json!({"address": 0x200, "length": 2}));
+    assert_eq!(rom, json!([0x6E, 0x05]));
+
+    result(&mut session, "run", json!({"frames": 60}));
+    let screen = result(&mut session, "screen", Value::Null);
+    assert_eq!(screen["width"], 64);
+    assert_eq!(screen["rows"].as_array().unwrap().len(), 32);
+    assert!(screen["rows"].as_array().unwrap().iter().any(|row| row != "0000000000000000"));
+
+    result(&mut session, "step", json!({"count": 3}));
+    result(&mut session, "key_down", json!({"key": 4}));
+    result(&mut session, "key_up", json!({"key": 4}));
+    let registers = result(&mut session, "registers", Value::Null);
+    assert_eq!(registers["pc"], session.cpu.pc());
+    assert_eq!(registers["v0"], session.cpu.v()[0]);
+}
+
+#[test]
+fn change_memory_and_registers() {
+    let mut session = session();
+    result(&mut session, "poke", json!({"address": 0x300, "bytes": [1, 2, 3]}));
+    let bytes = result(&mut session, "peek", json!({"address": 0x300, "length": 3}));
+    assert_eq!(bytes, json!([1, 2, 3]));
+
+    let changes = json!({"va": 7, "i": 0x345, "pc": 0x208, "delay": 30});
+    result(&mut session, "set_registers", changes);
+    let registers = result(&mut session, "registers", Value::Null);
+    assert_eq!(registers["va"], 7);
+    assert_eq!(registers["i"], 0x345);
+    assert_eq!(registers["pc"], 0x208);
+    assert_eq!(registers["delay"], 30);
+
+    // Nothing changes when one of the registers is unknown
+    let changes = json!({"v1": 1, "vg": 2});
+    assert_eq!(error(&mut session, "set_registers", changes), -32602);
+    assert_eq!(session.cpu.v()[1], 0);
+
+    // Writing over the next instruction changes what runs: 6A2A sets VA to 42
+    result(&mut session, "poke", json!({"address": 0x208, "bytes": [0x6A, 0x2A]}));
+    result(&mut session, "step", Value::Null);
+    assert_eq!(session.cpu.v()[0xA], 42);
+}
+
+#[test]
+fn save_and_load_state() {
+    let mut session = session();
+    result(&mut session, "run", json!({"frames": 30}));
+    let state = result(&mut session, "save_state", Value::Null);
+    let registers = result(&mut session, "registers", Value::Null);
+
+    result(&mut session, "reset", Value::Null);
+    result(&mut session, "load_state", state.clone());
+    assert_eq!(result(&mut session, "registers", Value::Null), registers);
+
+    let damaged = json!({"state": &state["state"].as_str().unwrap()[..100]});
+    assert_eq!(error(&mut session, "load_state", damaged), -32000);
+    let not_hex = json!({"state": "xyz"});
+    assert_eq!(error(&mut session, "load_state", not_hex), -32602);
+}
+
+#[test]
+fn faults_and_bad_requests() {
+    let mut session = session();
+    // An unknown opcode stops the program until it is reset
+    result(&mut session, "poke", json!({"address": 0x200, "bytes": [0xFF, 0xFF]}));
+    assert_eq!(error(&mut session, "step", Value::Null), -32000);
+    assert_eq!(error(&mut session, "run", Value::Null), -32000);
+    result(&mut session, "reset", Value::Null);
+    result(&mut session, "step", Value::Null);
+
+    assert_eq!(error(&mut session, "jump", Value::Null), -32601);
+    assert_eq!(error(&mut session, "key_down", json!({"key": 16})), -32602);
+    assert_eq!(error(&mut session, "peek", json!({"address": 0xFFF, "length": 2})), -32602);
+    // One request can't run forever
+    assert_eq!(error(&mut session, "step", json!({"count": 1_000_001})), -32602);
+    assert_eq!(error(&mut session, "run", json!({"frames": 3601})), -32602);
+    assert_eq!(error(&mut session, "run", json!({"frames": u32::MAX})), -32602);
+    assert_eq!(error(&mut session, "load", json!({"path": "no such ROM"})), -32000);
+
+    let response: Value = serde_json::from_str(&session.handle("{").unwrap()).unwrap();
+    assert_eq!(response["error"]["code"], -32700);
+    // Notifications are carried out without an answer
+    let notification = r#"{"jsonrpc": "2.0", "method": "key_down", "params": {"key": 1}}"#;
+    assert_eq!(session.handle(notification), None);
+}
+
+#[test]
+fn serve_over_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut session = session();
+        let (stream, _) = listener.accept().unwrap();
+        session.serve_client(stream).unwrap();
+        session.cpu.pc()
+    });
+
+    let mut client = TcpStream::connect(address).unwrap();
+    let mut responses = BufReader::new(client.try_clone().unwrap());
+    let mut line = String::new();
+    for request in &[request("run", json!({"frames": 10})), request("registers", Value::Null)] {
+        client.write_all(request.as_bytes()).unwrap();
+        client.write_all(b"\n").unwrap();
+        line.clear();
+        responses.read_line(&mut line).unwrap();
+    }
+    let response: Value = serde_json::from_str(&line).unwrap();
+    drop(client);
+    drop(responses);
+
+    assert_eq!(response["result"]["pc"], server.join().unwrap());
+}
+
+#[test]
+fn only_loopback_addresses() {
+    let mut session = Session::new(Chip8::new(), 10, remote::load_rom);
+    assert!(remote::serve("0.0.0.0:0", &mut session, |_| ()).is_err());
+}
+
+// A socket left behind by an earlier server doesn't keep a new one from starting
+#[cfg(unix)]
+#[test]
+fn stale_unix_socket() {
+    let dir = std::env::temp_dir().join(format!("chipper-remote-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("socket").to_string_lossy().into_owned();
+    drop(UnixListener::bind(&path).unwrap());
+
+    let server_path = path.clone();
+    thread::spawn(move || {
+        let mut session = session();
+        remote::serve(&server_path, &mut session, |_| ()).unwrap();
+    });
+
+    // The server removes the old socket before binding its own
+    let mut client = None;
+    for _ in 0..100 {
+        thread::sleep(std::time::Duration::from_millis(10));
+        if let Ok(stream) = UnixStream::connect(&path) {
+            client = Some(stream);
+            break;
+        }
+    }
+    let mut client = client.expect("the server didn't start");
+    client.write_all(request("registers", Value::Null).as_bytes()).unwrap();
+    client.write_all(b"\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(client).read_line(&mut line).unwrap();
+    let response: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(response["result"]["pc"], 0x200);
+
+    // Other files are never removed
+    let file = dir.join("file").to_string_lossy().into_owned();
+    File::create(&file).unwrap();
+    let mut session = Session::new(Chip8::new(), 10, remote::load_rom);
+    assert!(remote::serve(&file, &mut session, |_| ()).is_err());
+    assert!(fs::metadata(&file).unwrap().is_file());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
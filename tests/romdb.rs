@@ -0,0 +1,34 @@
+extern crate chipper;
+
+mod common;
+
+use chipper::romdb::{self, Database};
+
+const HASHES: &str = r#"{"0123": 0}"#;
+
+fn programs(keys: &str) -> String {
+    format!(r#"[{{"title": "Game", "roms": {{"0123": {{"platforms": ["modernChip8"],
+                                                      "keys": {}}}}}}}]"#,
+            keys)
+}
+
+#[test]
+fn bundled_database() {
+    let info = Database::bundled().find(&romdb::sha1(&common::rom("BRIX"))).unwrap();
+    assert_eq!(info.title, "Brix");
+}
+
+#[test]
+fn action_keys() {
+    let database = Database::parse(HASHES, &programs(r#"{"left": 4, "a": 15}"#)).unwrap();
+    let info = database.find("0123").unwrap();
+    assert_eq!(info.keys.get("left"), Some(&4));
+    assert_eq!(info.keys.get("a"), Some(&15));
+}
+
+#[test]
+fn keys_off_the_keypad_are_rejected() {
+    let error = Database::parse(HASHES, &programs(r#"{"left": 20}"#)).err().unwrap();
+    assert_eq!(error,
+               "programs.json: Game binds \"left\" to 20, which is not a CHIP-8 key (0-F)");
+}
@@ -0,0 +1,85 @@
+extern crate chipper;
+
+mod common;
+
+use chipper::cpu::{Chip8, Fault};
+
+fn machine() -> Chip8 {
+    let mut cpu = Chip8::new();
+    cpu.seed(1);
+    cpu.load_rom(&common::rom("BRIX"));
+    cpu
+}
+
+#[test]
+fn save_and_load() {
+    let mut cpu = machine();
+    for _ in 0..100 {
+        cpu.run_frame(10).unwrap();
+    }
+    let state = cpu.save_state();
+
+    let mut restored = machine();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.save_state(), state);
+    assert!(restored.gfx == cpu.gfx);
+    assert_eq!(restored.pc(), cpu.pc());
+
+    // The restored machine runs on from where the state was saved
+    restored.run_frame(10).unwrap();
+    assert!(restored.gfx.is_dirty());
+}
+
+#[test]
+fn damaged_states_are_rejected() {
+    let mut cpu = machine();
+    let state = cpu.save_state();
+
+    assert!(cpu.load_state(b"garbage").is_err());
+    assert!(cpu.load_state(&state[..state.len() - 1]).is_err());
+
+    let mut long = state.clone();
+    long.push(0);
+    assert!(cpu.load_state(&long).is_err());
+
+    // Stack pointer past the end of the stack
+    let mut damaged = state.clone();
+    let sp = 4 + 4096 + 16 + 6;
+    damaged[sp] = 17;
+    assert!(cpu.load_state(&damaged).is_err());
+
+    // Program counter and return address past the end of memory
+    let pc = sp - 2;
+    let mut damaged = state.clone();
+    damaged[pc..pc + 2].copy_from_slice(&0x1001u16.to_le_bytes());
+    assert!(cpu.load_state(&damaged).is_err());
+
+    let mut damaged = state.clone();
+    damaged[sp..sp + 2].copy_from_slice(&1u16.to_le_bytes());
+    damaged[sp + 2..sp + 4].copy_from_slice(&0xFFFFu16.to_le_bytes());
+    assert!(cpu.load_state(&damaged).is_err());
+
+    assert_eq!(cpu.save_state(), state);
+}
+
+// A machine that stopped because its program counter left memory can be saved and loaded
+#[test]
+fn save_and_load_after_leaving_memory() {
+    // The last instruction in memory, 6000 runs on to 0x1000 and 3000 skips to 0x1002
+    for &(last, stopped_at) in &[(0x6000u16, 0x1000), (0x3000, 0x1002)] {
+        let mut cpu = Chip8::new();
+        cpu.wrap_addresses = false;
+        cpu.load_rom(&[0x1F, 0xFE]);
+        cpu.poke(0xFFE, (last >> 8) as u8);
+        cpu.poke(0xFFF, last as u8);
+        assert_eq!(cpu.run_frame(10), Err(Fault::AddressOutOfRange(stopped_at)));
+        let state = cpu.save_state();
+
+        let mut restored = Chip8::new();
+        restored.wrap_addresses = false;
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+        assert_eq!(restored.pc(), 0x1000);
+        assert_eq!(restored.run_frame(10), Err(Fault::AddressOutOfRange(0x1000)));
+    }
+}